@@ -21,6 +21,7 @@ typedef enum vibrant_error {
   NullName,
   BadName,
   OutOfRange,
+  Connection,
 } vibrant_error;
 
 enum vibrant_error vibrant_instance_new(const vibrant_instance **_ret);
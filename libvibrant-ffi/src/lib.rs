@@ -1,3 +1,6 @@
+// every function here is called from C, which can not see rust's unsafe anyway
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
 use libvibrant::Instance;
 use libvibrant::ControllerBackend;
 use std::ptr::null_mut;
@@ -10,7 +13,8 @@ pub enum Error {
     OpenDisplay,
     NullName,
     BadName,
-    OutOfRange
+    OutOfRange,
+    Connection
 }
 
 #[repr(C)]
@@ -65,7 +69,7 @@ pub extern "C" fn vibrant_instance_free(instance: *mut Instance) {
     }
 
     unsafe {
-        Box::from_raw(instance);
+        drop(Box::from_raw(instance));
     }
 }
 
@@ -91,10 +95,15 @@ pub extern "C" fn vibrant_instance_get_controller_saturation(instance: *mut Inst
     let controllers = instance.controllers();
 
     if idx < controllers.len() {
-        unsafe {
-            *saturation = controllers.get(idx).unwrap().get_saturation(instance);
+        match controllers.get(idx).unwrap().get_saturation() {
+            Ok(value) => {
+                unsafe {
+                    *saturation = value;
+                }
+                Error::Ok
+            }
+            Err(_) => Error::Connection
         }
-        Error::Ok
     }
     else {
         Error::OutOfRange
@@ -111,8 +120,10 @@ pub extern "C" fn vibrant_instance_set_controller_saturation(instance: *mut Inst
     let controllers = instance.controllers();
 
     if idx < controllers.len() {
-        controllers.get(idx).unwrap().set_saturation(instance, saturation);
-        Error::Ok
+        match controllers.get(idx).unwrap().set_saturation(saturation) {
+            Ok(()) => Error::Ok,
+            Err(_) => Error::Connection
+        }
     }
     else {
         Error::OutOfRange
@@ -0,0 +1,391 @@
+//! Gradual changes of saturation and color transforms.
+//!
+//! An [`Animator`] steps displays towards a target on a thread of its own, so a change of
+//! profile fades in instead of snapping. Giving a display a new target while it is still moving
+//! continues from wherever it is at that moment. Displays dimmed with
+//! [`ControllerRef::set_brightness`] stay dimmed while they move.
+//!
+//! ```no_run
+//! use libvibrant::animation::{Animator, Easing};
+//! use libvibrant::profiles::Adjustment;
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//!
+//! let instance = Arc::new(libvibrant::Instance::new().unwrap());
+//! let animator = Animator::new(instance.clone());
+//! for controller in instance.controllers() {
+//!     animator.transition(&controller, Adjustment::Saturation(2.0), Duration::from_millis(300),
+//!                         Easing::EaseInOut).unwrap();
+//! }
+//! animator.wait().unwrap();
+//! ```
+
+use crate::instance::{saturation_matrix, ColorMatrix, ControllerBackend, ControllerRef, Error,
+                      Instance};
+use crate::profiles::Adjustment;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How often displays are changed while they move, about 60 times a second.
+const FRAME: Duration = Duration::from_millis(16);
+
+/// How the progress of a transition is spread over its duration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Easing {
+    /// The same change every frame.
+    Linear,
+    /// Starts slowly, speeds up and slows down again towards the end.
+    EaseInOut,
+    /// Most of the change happens right away, then it settles slowly.
+    Exponential
+}
+
+impl Easing {
+    /// Maps the elapsed part of the duration, in [0.0, 1.0], to how far the value should have
+    /// moved, in [0.0, 1.0] as well.
+    pub fn apply(&self, t: f64) -> f64 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseInOut => {
+                if t < 0.5 {
+                    4.0 * t * t * t
+                }
+                else {
+                    1.0 - (2.0 - 2.0 * t).powi(3) / 2.0
+                }
+            }
+            // scaled so it actually arrives at 1
+            Easing::Exponential => (1.0 - 2f64.powf(-10.0 * t)) / (1.0 - 2f64.powi(-10))
+        }
+    }
+}
+
+/// Returns the value between two adjustments, mixing a saturation with a matrix through the
+/// matrix of that saturation.
+fn interpolate(from: &Adjustment, to: &Adjustment, progress: f64) -> Adjustment {
+    let matrix = |adjustment: &Adjustment| match adjustment {
+        Adjustment::Saturation(saturation) => saturation_matrix(*saturation),
+        Adjustment::Transform(matrix) => *matrix
+    };
+
+    match (from, to) {
+        (Adjustment::Saturation(from), Adjustment::Saturation(to)) => {
+            Adjustment::Saturation(from + (to - from) * progress)
+        }
+        _ => {
+            let (from, to) = (matrix(from), matrix(to));
+            let mut result: ColorMatrix = [[0.0; 3]; 3];
+            for (row, values) in result.iter_mut().enumerate() {
+                for (column, value) in values.iter_mut().enumerate() {
+                    *value = from[row][column] + (to[row][column] - from[row][column]) * progress;
+                }
+            }
+            Adjustment::Transform(result)
+        }
+    }
+}
+
+/// A display on its way to a target.
+struct Transition {
+    /// Tells a transition apart from the one that replaced it.
+    id: u64,
+    output: u32,
+    from: Adjustment,
+    to: Adjustment,
+    start: Instant,
+    duration: Duration,
+    easing: Easing,
+    /// What was set last, frames that would set the same value again are skipped.
+    current: Adjustment
+}
+
+impl Transition {
+    /// Returns where the display should be at the given time, and if it arrived.
+    fn frame(&self, now: Instant) -> (Adjustment, bool) {
+        let elapsed = now.saturating_duration_since(self.start);
+        if elapsed >= self.duration {
+            return (self.to.clone(), true);
+        }
+        let t = elapsed.as_secs_f64() / self.duration.as_secs_f64();
+        (interpolate(&self.from, &self.to, self.easing.apply(t)), false)
+    }
+}
+
+#[derive(Default)]
+struct State {
+    transitions: Vec<Transition>,
+    next_id: u64,
+    /// The first error since the last call to wait.
+    error: Option<Error>,
+    stop: bool
+}
+
+struct Shared {
+    state: Mutex<State>,
+    /// Signalled when a transition is added or all of them are done.
+    changed: Condvar
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+}
+
+/// Moves displays towards their targets over time, see the [module](self) documentation.
+///
+/// Dropping the animator leaves displays that are still moving where they are.
+pub struct Animator {
+    instance: Arc<Instance>,
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>
+}
+
+impl Animator {
+    /// Starts the thread that changes the displays of the instance.
+    pub fn new(instance: Arc<Instance>) -> Animator {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State::default()),
+            changed: Condvar::new()
+        });
+        let thread = thread::Builder::new()
+            .name("libvibrant-animator".to_string())
+            .spawn({
+                let instance = instance.clone();
+                let shared = shared.clone();
+                move || run(&instance, &shared)
+            })
+            .expect("failed to start the animator thread");
+
+        Animator {
+            instance,
+            shared,
+            thread: Some(thread)
+        }
+    }
+
+    /// Moves a display to the target over the given duration. If the display is already moving,
+    /// that transition is cancelled and the new one starts from where it got to.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ForeignController`] if the controller is not one of the instance of the
+    /// animator, [`Error::Unsupported`] if the target is a matrix and the display only supports
+    /// saturation, or an error if the server failed to answer. A transition that is running is
+    /// left alone when an error is returned. Errors while the display moves are returned by
+    /// [`Animator::wait`].
+    pub fn transition(&self, controller: &ControllerRef<'_>, target: Adjustment,
+                      duration: Duration, easing: Easing) -> Result<(), Error> {
+        if !self.owns(controller) {
+            return Err(Error::ForeignController(controller.get_name().to_string()));
+        }
+        if let (ControllerBackend::XNVCtrl, Adjustment::Transform(_)) =
+            (controller.get_backend(), &target) {
+            return Err(Error::Unsupported("color transform matrices"));
+        }
+
+        let output = controller.get_output_id();
+        let mut state = self.shared.lock();
+        let running = state.transitions.iter()
+            .find(|transition| transition.output == output)
+            .map(|transition| transition.current.clone());
+        let from = match running {
+            Some(current) => current,
+            None => {
+                // don't hold up the thread while asking the server
+                drop(state);
+                let current = Adjustment::current(controller)?;
+                state = self.shared.lock();
+                current
+            }
+        };
+
+        state.transitions.retain(|transition| transition.output != output);
+        state.next_id += 1;
+        let id = state.next_id;
+        state.transitions.push(Transition {
+            id,
+            output,
+            current: from.clone(),
+            from,
+            to: target,
+            start: Instant::now(),
+            duration,
+            easing
+        });
+        self.shared.changed.notify_all();
+        Ok(())
+    }
+
+    /// Stops a display where it is. Controllers of other instances are ignored.
+    pub fn cancel(&self, controller: &ControllerRef<'_>) {
+        if !self.owns(controller) {
+            return;
+        }
+        let output = controller.get_output_id();
+        self.shared.lock().transitions.retain(|transition| transition.output != output);
+        self.shared.changed.notify_all();
+    }
+
+    /// Returns if a controller belongs to the instance the animator changes displays through.
+    fn owns(&self, controller: &ControllerRef<'_>) -> bool {
+        std::ptr::eq(controller.instance(), &*self.instance)
+    }
+
+    /// Returns if any display is still moving.
+    pub fn is_running(&self) -> bool {
+        !self.shared.lock().transitions.is_empty()
+    }
+
+    /// Blocks until every display reached its target.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error that happened while changing a display since the last call. The
+    /// display it happened on stops moving.
+    pub fn wait(&self) -> Result<(), Error> {
+        let mut state = self.shared.lock();
+        while !state.transitions.is_empty() {
+            state = self.shared.changed.wait(state).unwrap();
+        }
+        match state.error.take() {
+            Some(error) => Err(error),
+            None => Ok(())
+        }
+    }
+
+    /// Returns the instance whose displays are changed.
+    pub fn instance(&self) -> &Arc<Instance> {
+        &self.instance
+    }
+}
+
+impl Drop for Animator {
+    fn drop(&mut self) {
+        self.shared.lock().stop = true;
+        self.shared.changed.notify_all();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Returns the value a display ends up with, so frames that would not change it can be skipped.
+fn quantize(controller: &ControllerRef<'_>, adjustment: Adjustment) -> Adjustment {
+    match adjustment {
+        Adjustment::Saturation(saturation) => {
+            Adjustment::Saturation(controller.quantize_saturation(saturation))
+        }
+        transform => transform
+    }
+}
+
+/// The animator thread, sets the next frame of every transition until it is told to stop.
+fn run(instance: &Instance, shared: &Shared) {
+    let mut state = shared.lock();
+    loop {
+        if state.stop {
+            return;
+        }
+        if state.transitions.is_empty() {
+            state = shared.changed.wait(state).unwrap();
+            continue;
+        }
+
+        let now = Instant::now();
+        let controllers = instance.controllers();
+        let mut frames = Vec::with_capacity(state.transitions.len());
+        for transition in &state.transitions {
+            let (adjustment, done) = transition.frame(now);
+            let controller = controllers.iter()
+                .find(|controller| controller.get_output_id() == transition.output);
+            // NVIDIA displays only take whole steps, most frames of slow transitions are the same
+            let unchanged = controller.is_some_and(|controller| {
+                quantize(controller, adjustment.clone()) == transition.current
+            });
+            if !unchanged || done {
+                frames.push((transition.id, adjustment, done, controller));
+            }
+        }
+        // setting values takes round trips, callers shouldn't wait for those
+        drop(state);
+
+        let results: Vec<_> = frames.into_iter()
+            .map(|(id, adjustment, done, controller)| {
+                let result = match controller {
+                    Some(controller) => adjustment.apply(controller)
+                        .map(|()| quantize(controller, adjustment))
+                        .map_err(Some),
+                    // the display went away, there is nothing left to move
+                    None => Err(None)
+                };
+                (id, done, result)
+            })
+            .collect();
+
+        state = shared.lock();
+        for (id, done, result) in results {
+            // cancelled or replaced in the meantime
+            let idx = match state.transitions.iter().position(|transition| transition.id == id) {
+                Some(idx) => idx,
+                None => continue
+            };
+            match result {
+                Ok(_) if done => {
+                    state.transitions.remove(idx);
+                }
+                Ok(current) => state.transitions[idx].current = current,
+                Err(error) => {
+                    state.transitions.remove(idx);
+                    if state.error.is_none() {
+                        state.error = error;
+                    }
+                }
+            }
+        }
+        if state.transitions.is_empty() {
+            shared.changed.notify_all();
+            continue;
+        }
+
+        state = shared.changed.wait_timeout(state, FRAME).unwrap().0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn eases() {
+        for easing in [Easing::Linear, Easing::EaseInOut, Easing::Exponential] {
+            assert_eq!(easing.apply(0.0), 0.0);
+            assert!((easing.apply(1.0) - 1.0).abs() < 1e-12);
+            let steps: Vec<f64> = (0..=100).map(|step| easing.apply(step as f64 / 100.0))
+                .collect();
+            assert!(steps.windows(2).all(|pair| pair[0] <= pair[1]), "{:?}", easing);
+        }
+        assert_eq!(Easing::EaseInOut.apply(0.5), 0.5);
+        assert!(Easing::Exponential.apply(0.2) > 0.7);
+    }
+
+    #[test]
+    fn interpolates() {
+        let halfway = interpolate(&Adjustment::Saturation(1.0), &Adjustment::Saturation(3.0), 0.5);
+        assert_eq!(halfway, Adjustment::Saturation(2.0));
+
+        let identity = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+        let halfway = interpolate(&Adjustment::Transform(identity), &Adjustment::Saturation(0.0),
+                                  0.5);
+        let third = 1.0 / 3.0;
+        match halfway {
+            Adjustment::Transform(matrix) => {
+                assert!((matrix[0][0] - (1.0 + third) / 2.0).abs() < 1e-12);
+                assert!((matrix[0][1] - third / 2.0).abs() < 1e-12);
+            }
+            other => panic!("expected a matrix, got {:?}", other)
+        }
+    }
+}
@@ -0,0 +1,238 @@
+//! Async versions of the blocking calls of [`Instance`] for use with tokio, enabled by the `async`
+//! feature.
+//!
+//! Round trips to the server run on tokio's blocking pool so they never stall the runtime, events
+//! are read whenever the socket of the X connection becomes readable.
+
+use crate::{BacklightRange, ColorMatrix, ControllerBackend, ControllerRef, Error, Event, Instance};
+use crate::instance::Controller;
+use futures_core::Stream;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::panic;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use tokio::io::Interest;
+use tokio::io::unix::AsyncFd;
+use tokio::task;
+use x11rb::errors::ConnectionError;
+
+/// An [`Instance`] that can be used from async code.
+pub struct AsyncInstance {
+    instance: Arc<Instance>,
+    /// The task waiting on the event stream. Blocking calls may read events off the socket while
+    /// the stream is not looking, so they wake it once they are done.
+    stream_waker: Arc<Mutex<Option<Waker>>>
+}
+
+/// Runs a blocking call against the instance on the blocking pool.
+async fn blocking<T, F>(instance: &Arc<Instance>, stream_waker: &Mutex<Option<Waker>>,
+                        f: F) -> Result<T, Error>
+    where T: Send + 'static, F: FnOnce(&Instance) -> Result<T, Error> + Send + 'static {
+    let instance = instance.clone();
+    let result = match task::spawn_blocking(move || f(&instance)).await {
+        Ok(result) => result,
+        Err(err) => panic::resume_unwind(err.into_panic())
+    };
+
+    if let Some(waker) = stream_waker.lock().unwrap().take() {
+        waker.wake();
+    }
+    result
+}
+
+impl AsyncInstance {
+    pub fn new(instance: Instance) -> AsyncInstance {
+        AsyncInstance {
+            instance: Arc::new(instance),
+            stream_waker: Arc::new(Mutex::new(None))
+        }
+    }
+
+    /// Returns the wrapped instance.
+    pub fn instance(&self) -> &Arc<Instance> {
+        &self.instance
+    }
+
+    /// Returns a list of controllers that correspond to displays that have a controllable backend.
+    pub fn controllers(&self) -> Vec<AsyncController> {
+        self.instance.controllers().iter()
+            .map(|controller| AsyncController {
+                instance: self.instance.clone(),
+                controller: controller.inner().clone(),
+                stream_waker: self.stream_waker.clone()
+            })
+            .collect()
+    }
+
+    /// Async version of [`Instance::refresh`].
+    pub async fn refresh(&self) -> Result<(), Error> {
+        blocking(&self.instance, &self.stream_waker, |instance| instance.refresh()).await
+    }
+
+    /// Selects events on the instance and returns a stream of them. Only one stream should be
+    /// used at a time, since every event is only delivered once.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the server refused to send us events, or if this is called outside of
+    /// a tokio runtime.
+    pub fn events(&self) -> Result<EventStream, Error> {
+        self.instance.select_events()?;
+        // SAFETY: the socket belongs to the connection, which lives as long as Socket does
+        let socket = unsafe {
+            AsyncFd::register_with_interest(Socket(self.instance.clone()), Interest::READABLE)
+        }.map_err(|err| ConnectionError::from(err.into_parts().1))?;
+
+        Ok(EventStream {
+            socket,
+            waker: self.stream_waker.clone()
+        })
+    }
+}
+
+/// The async counterpart of a [`ControllerRef`]. It keeps its instance alive, so it can be moved
+/// into spawned tasks.
+#[derive(Clone)]
+pub struct AsyncController {
+    instance: Arc<Instance>,
+    controller: Arc<dyn Controller>,
+    stream_waker: Arc<Mutex<Option<Waker>>>
+}
+
+impl AsyncController {
+    /// Returns the blocking handle of this controller.
+    pub fn blocking(&self) -> ControllerRef<'_> {
+        ControllerRef::new(&self.instance, self.controller.clone())
+    }
+
+    /// Async version of [`ControllerRef::get_saturation`].
+    pub async fn get_saturation(&self) -> Result<f64, Error> {
+        let controller = self.controller.clone();
+        blocking(&self.instance, &self.stream_waker,
+                 move |instance| ControllerRef::new(instance, controller).get_saturation()).await
+    }
+
+    /// Async version of [`ControllerRef::set_saturation`].
+    pub async fn set_saturation(&self, saturation: f64) -> Result<(), Error> {
+        let controller = self.controller.clone();
+        blocking(&self.instance, &self.stream_waker,
+                 move |instance| {
+                     ControllerRef::new(instance, controller).set_saturation(saturation)
+                 }).await
+    }
+
+    /// Async version of [`ControllerRef::get_brightness`].
+    pub async fn get_brightness(&self) -> Result<f64, Error> {
+        let controller = self.controller.clone();
+        blocking(&self.instance, &self.stream_waker,
+                 move |instance| ControllerRef::new(instance, controller).get_brightness()).await
+    }
+
+    /// Async version of [`ControllerRef::set_brightness`].
+    pub async fn set_brightness(&self, level: f64) -> Result<(), Error> {
+        let controller = self.controller.clone();
+        blocking(&self.instance, &self.stream_waker,
+                 move |instance| ControllerRef::new(instance, controller).set_brightness(level))
+            .await
+    }
+
+    /// Returns the raw values the backlight of the display takes, see
+    /// [`ControllerRef::backlight_range`].
+    pub fn backlight_range(&self) -> Option<BacklightRange> {
+        self.blocking().backlight_range()
+    }
+
+    /// Async version of [`ControllerRef::get_backlight`].
+    pub async fn get_backlight(&self) -> Result<f64, Error> {
+        let controller = self.controller.clone();
+        blocking(&self.instance, &self.stream_waker,
+                 move |instance| ControllerRef::new(instance, controller).get_backlight()).await
+    }
+
+    /// Async version of [`ControllerRef::set_backlight`].
+    pub async fn set_backlight(&self, level: f64) -> Result<(), Error> {
+        let controller = self.controller.clone();
+        blocking(&self.instance, &self.stream_waker,
+                 move |instance| ControllerRef::new(instance, controller).set_backlight(level))
+            .await
+    }
+
+    /// Async version of [`ControllerRef::get_color_transform`].
+    pub async fn get_color_transform(&self) -> Result<ColorMatrix, Error> {
+        let controller = self.controller.clone();
+        blocking(&self.instance, &self.stream_waker,
+                 move |instance| ControllerRef::new(instance, controller).get_color_transform())
+            .await
+    }
+
+    /// Async version of [`ControllerRef::set_color_transform`].
+    pub async fn set_color_transform(&self, matrix: ColorMatrix) -> Result<(), Error> {
+        let controller = self.controller.clone();
+        blocking(&self.instance, &self.stream_waker,
+                 move |instance| {
+                     ControllerRef::new(instance, controller).set_color_transform(&matrix)
+                 }).await
+    }
+
+    /// Returns the name of the screen.
+    pub fn get_name(&self) -> &str {
+        self.controller.get_name()
+    }
+
+    /// Returns the id of the RandR output this controller is attached to.
+    pub fn get_output_id(&self) -> u32 {
+        self.controller.get_output_id()
+    }
+
+    /// Returns the ids of every RandR output this controller sets.
+    pub fn get_output_ids(&self) -> Vec<u32> {
+        self.controller.get_output_ids()
+    }
+
+    /// Returns the backend used for this controller.
+    pub fn get_backend(&self) -> ControllerBackend {
+        self.controller.get_backend()
+    }
+}
+
+/// Keeps the connection, and with it its socket, alive for as long as tokio watches it.
+struct Socket(Arc<Instance>);
+
+impl AsRawFd for Socket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+/// A stream of the [`Event`]s of an instance, created by [`AsyncInstance::events`].
+pub struct EventStream {
+    socket: AsyncFd<Socket>,
+    waker: Arc<Mutex<Option<Waker>>>
+}
+
+impl Stream for EventStream {
+    type Item = Result<Event, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        *this.waker.lock().unwrap() = Some(cx.waker().clone());
+
+        loop {
+            match this.socket.get_ref().0.poll_event() {
+                Ok(Some(event)) => return Poll::Ready(Some(Ok(event))),
+                Ok(None) => {},
+                Err(err) => return Poll::Ready(Some(Err(err)))
+            }
+
+            // nothing is queued, wait for the server to send something
+            match this.socket.poll_read_ready(cx) {
+                Poll::Ready(Ok(mut guard)) => guard.clear_ready(),
+                Poll::Ready(Err(err)) => {
+                    return Poll::Ready(Some(Err(ConnectionError::from(err).into())))
+                }
+                Poll::Pending => return Poll::Pending
+            }
+        }
+    }
+}
@@ -0,0 +1,113 @@
+//! Building blocks for color transform matrices.
+//!
+//! Matrices compose by multiplication, [`multiply`]`(a, b)` applies `b` first and `a` to its
+//! result. Every matrix here keeps white at or below full brightness, so composing them never
+//! clips.
+
+use crate::instance::{saturation_matrix, ColorMatrix};
+
+/// The matrix that leaves every color unchanged.
+pub const IDENTITY: ColorMatrix = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+
+/// The color temperature of daylight, which [`temperature`] leaves unchanged.
+pub const NEUTRAL_TEMPERATURE: f64 = 6500.0;
+
+/// Returns the matrix that sets a saturation, 1.0 leaves colors unchanged and 0.0 turns them to
+/// gray. It is the same the CTM backend uses for [`ControllerRef::set_saturation`].
+///
+/// [`ControllerRef::set_saturation`]: crate::ControllerRef::set_saturation
+pub fn saturation(saturation: f64) -> ColorMatrix {
+    saturation_matrix(saturation)
+}
+
+/// Returns the matrix that dims every color to the given level, 1.0 is full brightness.
+pub fn brightness(level: f64) -> ColorMatrix {
+    let level = level.clamp(0.0, 1.0);
+    [[level, 0.0, 0.0], [0.0, level, 0.0], [0.0, 0.0, level]]
+}
+
+/// Returns the color of a black body at the given temperature, as red, green and blue in
+/// [0.0, 1.0] with the brightest channel at 1.0. This is Tanner Helland's fit, which is close
+/// enough between 1000 K and 40000 K.
+fn black_body(kelvin: f64) -> [f64; 3] {
+    let t = kelvin.clamp(1000.0, 40000.0) / 100.0;
+    let red = if t <= 66.0 {
+        255.0
+    }
+    else {
+        329.698_727_446 * (t - 60.0).powf(-0.133_204_759_2)
+    };
+    let green = if t <= 66.0 {
+        99.470_802_586_1 * t.ln() - 161.119_568_166_1
+    }
+    else {
+        288.122_169_528_3 * (t - 60.0).powf(-0.075_514_849_2)
+    };
+    let blue = if t >= 66.0 {
+        255.0
+    }
+    else if t <= 19.0 {
+        0.0
+    }
+    else {
+        138.517_731_223_1 * (t - 10.0).ln() - 305.044_792_730_7
+    };
+
+    [red, green, blue].map(|channel| (channel / 255.0).clamp(0.0, 1.0))
+}
+
+/// Returns the matrix that shifts the white point to the given color temperature in Kelvin.
+/// [`NEUTRAL_TEMPERATURE`] leaves colors unchanged, lower temperatures are warmer.
+pub fn temperature(kelvin: f64) -> ColorMatrix {
+    let color = black_body(kelvin);
+    let neutral = black_body(NEUTRAL_TEMPERATURE);
+    let mut white = [0.0; 3];
+    for channel in 0..3 {
+        white[channel] = color[channel] / neutral[channel];
+    }
+    let max = white.iter().cloned().fold(f64::MIN, f64::max);
+
+    let mut matrix = [[0.0; 3]; 3];
+    for channel in 0..3 {
+        matrix[channel][channel] = white[channel] / max;
+    }
+    matrix
+}
+
+/// Returns the matrix that applies `b` and then `a`.
+pub fn multiply(a: &ColorMatrix, b: &ColorMatrix) -> ColorMatrix {
+    let mut result = [[0.0; 3]; 3];
+    for (row, values) in result.iter_mut().enumerate() {
+        for (column, value) in values.iter_mut().enumerate() {
+            *value = (0..3).map(|k| a[row][k] * b[k][column]).sum();
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_temperatures() {
+        let neutral = temperature(NEUTRAL_TEMPERATURE);
+        for (row, values) in neutral.iter().enumerate() {
+            for (column, value) in values.iter().enumerate() {
+                assert!((value - IDENTITY[row][column]).abs() < 1e-9);
+            }
+        }
+
+        // warm light keeps red and loses blue
+        let warm = temperature(3000.0);
+        assert_eq!(warm[0][0], 1.0);
+        assert!(warm[1][1] < 1.0 && warm[2][2] < warm[1][1]);
+        let cold = temperature(10000.0);
+        assert_eq!(cold[2][2], 1.0);
+        assert!(cold[0][0] < 1.0);
+
+        assert_eq!(multiply(&warm, &IDENTITY), warm);
+        let composed = multiply(&warm, &saturation(0.0));
+        assert!((composed[2][0] - warm[2][2] / 3.0).abs() < 1e-12);
+    }
+}
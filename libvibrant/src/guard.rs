@@ -0,0 +1,324 @@
+//! Temporary changes that are undone when they go out of scope.
+//!
+//! [`ControllerRef::set_saturation_scoped`] and [`ControllerRef::set_color_transform_scoped`]
+//! return a guard that puts the previous value back when it is dropped, including while a panic
+//! unwinds. A process that is killed by a signal or aborts on panic never runs those drops, for
+//! that [`install_panic_hook`] and [`install_signal_handler`] restore whatever the live guards
+//! changed through a new connection to the server.
+//!
+//! ```no_run
+//! let instance = libvibrant::Instance::new().unwrap();
+//! libvibrant::guard::install_panic_hook();
+//! for controller in instance.controllers() {
+//!     let _guard = controller.set_saturation_scoped(2.0).unwrap();
+//!     // the saturation goes back once _guard is dropped
+//! }
+//! ```
+
+use crate::instance::{ColorMatrix, ControllerRef, Error, Instance};
+use std::ffi::CString;
+use std::panic;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard, Once, PoisonError, TryLockError};
+use std::thread::{self, ThreadId};
+
+/// What a guard puts back.
+#[derive(Debug, Clone, PartialEq)]
+enum Previous {
+    Saturation(f64),
+    Transform(ColorMatrix)
+}
+
+impl Previous {
+    fn apply(&self, controller: &ControllerRef<'_>) -> Result<(), Error> {
+        match self {
+            Previous::Saturation(saturation) => controller.set_saturation(*saturation),
+            Previous::Transform(matrix) => controller.set_color_transform(matrix)
+        }
+    }
+}
+
+/// A guard that is alive, as the hooks see it. They can't use the instance of the guard, which
+/// may be borrowed or in a broken state, so they find the display again by these.
+struct Pending {
+    id: u64,
+    thread: ThreadId,
+    display: Option<String>,
+    output: u32,
+    previous: Previous
+}
+
+static PENDING: Mutex<Vec<Pending>> = Mutex::new(Vec::new());
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Locks the registry, waiting for it if needed. Every change to it is a single push or remove,
+/// so it is fine to use after a panic poisoned it.
+fn lock_pending() -> MutexGuard<'static, Vec<Pending>> {
+    PENDING.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Adds a live guard to the registry, returns the id it is known by.
+fn register(display: Option<String>, output: u32, previous: Previous) -> u64 {
+    let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+    lock_pending().push(Pending {
+        id,
+        thread: thread::current().id(),
+        display,
+        output,
+        previous
+    });
+    id
+}
+
+/// Takes a guard out of the registry, returns false if a hook took it already.
+fn unregister(id: u64) -> bool {
+    let mut pending = lock_pending();
+    match pending.iter().position(|pending| pending.id == id) {
+        Some(idx) => {
+            pending.remove(idx);
+            true
+        }
+        None => false
+    }
+}
+
+/// Removes the guards from the registry that satisfy the predicate, for the hooks. They give up
+/// rather than wait if the registry is locked, the panic could have happened while this thread
+/// held it, and the process is about to go away anyway.
+fn try_take_pending<F: Fn(&Pending) -> bool>(take: F) -> Vec<Pending> {
+    let mut pending = match PENDING.try_lock() {
+        Ok(pending) => pending,
+        Err(TryLockError::Poisoned(error)) => error.into_inner(),
+        Err(TryLockError::WouldBlock) => return Vec::new()
+    };
+
+    let mut taken = Vec::new();
+    let mut idx = 0;
+    while idx < pending.len() {
+        if take(&pending[idx]) {
+            taken.push(pending.remove(idx));
+        }
+        else {
+            idx += 1;
+        }
+    }
+    taken
+}
+
+/// Puts back what the given guards changed, newest first so stacked guards end up at the oldest
+/// value. Returns the errors of the ones that could not be put back.
+fn restore_pending(mut pending: Vec<Pending>) -> Vec<Error> {
+    pending.sort_by_key(|pending| std::cmp::Reverse(pending.id));
+    let mut instances: Vec<(Option<String>, Instance)> = Vec::new();
+    let mut errors = Vec::new();
+
+    for pending in pending {
+        if !instances.iter().any(|(display, _)| *display == pending.display) {
+            let instance = match &pending.display {
+                Some(name) => CString::new(name.as_str())
+                    .map_err(|_| Error::OpenDisplay(name.clone()))
+                    .and_then(|name| Instance::from_display_name(&name)),
+                None => Instance::new()
+            };
+            match instance {
+                Ok(instance) => instances.push((pending.display.clone(), instance)),
+                Err(error) => {
+                    errors.push(error);
+                    continue;
+                }
+            }
+        }
+
+        let (_, instance) = instances.iter()
+            .find(|(display, _)| *display == pending.display)
+            .expect("connected above");
+        let controller = instance.controllers().into_iter()
+            .find(|controller| controller.get_output_id() == pending.output);
+        if let Some(controller) = controller {
+            if let Err(error) = pending.previous.apply(&controller) {
+                errors.push(error);
+            }
+        }
+    }
+    errors
+}
+
+/// Puts back what every live guard of the process changed. The guards stay alive but won't
+/// restore anything when dropped. Returns the errors of the displays that could not be put
+/// back, the others are restored regardless.
+///
+/// Nothing is restored if another thread is using the registry of guards right now, as this is
+/// meant to be called on the way out of the process.
+pub fn restore_all() -> Vec<Error> {
+    restore_pending(try_take_pending(|_| true))
+}
+
+/// Installs a panic hook that puts back what the live guards of the panicking thread changed,
+/// before the previous hook runs. Needed with `panic = "abort"`, where guards are never dropped.
+/// Installing it more than once has no effect.
+pub fn install_panic_hook() {
+    static INSTALLED: Once = Once::new();
+    INSTALLED.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let current = thread::current().id();
+            // the panic is what gets reported, a display that can't be restored has to wait
+            let _ = restore_pending(try_take_pending(|pending| pending.thread == current));
+            previous(info);
+        }));
+    });
+}
+
+/// Starts a thread that puts back what every live guard changed when one of the given signals
+/// arrives, like SIGINT or SIGTERM, and then lets the signal do what it does by default. The
+/// signals can't be handled in any other way by the process afterwards.
+///
+/// # Errors
+///
+/// Returns an error if one of the signals can't be handled, e.g. SIGKILL.
+#[cfg(feature = "signals")]
+pub fn install_signal_handler(signals: &[i32]) -> std::io::Result<()> {
+    let mut signals = signal_hook::iterator::Signals::new(signals)?;
+    thread::Builder::new()
+        .name("libvibrant-signals".to_string())
+        .spawn(move || {
+            for signal in signals.forever() {
+                let _ = restore_all();
+                let _ = signal_hook::low_level::emulate_default_handler(signal);
+            }
+        })?;
+    Ok(())
+}
+
+/// What both kinds of guards share.
+struct Guard<'a> {
+    controller: ControllerRef<'a>,
+    previous: Previous,
+    id: u64
+}
+
+impl<'a> Guard<'a> {
+    fn new(controller: ControllerRef<'a>, previous: Previous) -> Guard<'a> {
+        let id = register(controller.instance().display().name().map(str::to_string),
+                          controller.get_output_id(), previous.clone());
+        Guard {
+            controller,
+            previous,
+            id
+        }
+    }
+
+    /// Takes the guard out of the registry, returns false if a hook restored it already.
+    fn disarm(&self) -> bool {
+        unregister(self.id)
+    }
+
+    fn restore(&self) -> Result<(), Error> {
+        if self.disarm() {
+            self.previous.apply(&self.controller)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Guard<'_> {
+    fn drop(&mut self) {
+        // errors can't be returned from here, use restore() to see them
+        let _ = self.restore();
+    }
+}
+
+/// Puts the previous saturation of a display back when dropped, see
+/// [`ControllerRef::set_saturation_scoped`].
+#[must_use = "the saturation is put back as soon as the guard is dropped"]
+pub struct SaturationGuard<'a>(Guard<'a>);
+
+impl<'a> SaturationGuard<'a> {
+    pub(crate) fn new(controller: ControllerRef<'a>, previous: f64) -> SaturationGuard<'a> {
+        SaturationGuard(Guard::new(controller, Previous::Saturation(previous)))
+    }
+
+    pub fn controller(&self) -> &ControllerRef<'a> {
+        &self.0.controller
+    }
+
+    /// Returns the saturation that is put back.
+    pub fn previous(&self) -> f64 {
+        match self.0.previous {
+            Previous::Saturation(saturation) => saturation,
+            Previous::Transform(_) => unreachable!("saturation guards hold a saturation")
+        }
+    }
+
+    /// Puts the previous saturation back now.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the server rejected the saturation.
+    pub fn restore(self) -> Result<(), Error> {
+        self.0.restore()
+    }
+
+    /// Keeps the new saturation, nothing is put back.
+    pub fn keep(self) {
+        self.0.disarm();
+    }
+}
+
+/// Puts the previous color transform of a display back when dropped, see
+/// [`ControllerRef::set_color_transform_scoped`].
+#[must_use = "the color transform is put back as soon as the guard is dropped"]
+pub struct TransformGuard<'a>(Guard<'a>);
+
+impl<'a> TransformGuard<'a> {
+    pub(crate) fn new(controller: ControllerRef<'a>, previous: ColorMatrix) -> TransformGuard<'a> {
+        TransformGuard(Guard::new(controller, Previous::Transform(previous)))
+    }
+
+    pub fn controller(&self) -> &ControllerRef<'a> {
+        &self.0.controller
+    }
+
+    /// Returns the matrix that is put back.
+    pub fn previous(&self) -> &ColorMatrix {
+        match &self.0.previous {
+            Previous::Transform(matrix) => matrix,
+            Previous::Saturation(_) => unreachable!("transform guards hold a matrix")
+        }
+    }
+
+    /// Puts the previous color transform back now.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the server rejected the matrix.
+    pub fn restore(self) -> Result<(), Error> {
+        self.0.restore()
+    }
+
+    /// Keeps the new color transform, nothing is put back.
+    pub fn keep(self) {
+        self.0.disarm();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guards_of_many_threads_disarm() {
+        let threads: Vec<_> = (0..8)
+            .map(|output| thread::spawn(move || {
+                for _ in 0..1000 {
+                    let id = register(None, output, Previous::Saturation(1.0));
+                    // a busy registry must not make a guard forget to restore
+                    assert!(unregister(id));
+                }
+            }))
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+    }
+}
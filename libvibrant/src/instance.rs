@@ -1,32 +1,72 @@
+mod backlight;
 mod controller;
 mod error;
+mod event;
+mod gamma;
+mod identity;
+mod output_info;
+mod property;
+mod snapshot;
 mod xwrapper;
 
-pub use controller::Controller;
+pub use controller::ControllerRef;
+#[cfg(feature = "async")]
+pub(crate) use controller::Controller;
 pub use crate::instance::error::Error;
-pub use controller::ControllerBackend;
+pub use controller::{ColorMatrix, ControllerBackend};
+pub(crate) use controller::saturation_matrix;
+pub use event::Event;
+pub use backlight::BacklightRange;
+pub use gamma::GammaRamp;
+pub use identity::Identity;
+pub use output_info::{OutputInfo, ModeInfo, Rotation};
+pub use property::{BroadcastRgb, OutputProperty, PropertyValue, ValidValues};
+pub use snapshot::{ControllerState, DisplayState};
+use crate::instance::controller::ControllerList;
 use crate::instance::xwrapper::Display;
 use std::ffi::CStr;
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, RawFd};
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::ConnectionExt as _;
+use x11rb::rust_connection::RustConnection;
 
 /// A libvibrant instance. Holds a connection the X server and a list of displays that have
 /// available controllers.
+///
+/// An instance is `Send` and `Sync`, every method only needs `&self` so it can be shared between
+/// threads through an `Arc`.
 pub struct Instance {
     xcon: Display,
-    controllers: Vec<Box<dyn Controller>>
+    controllers: RwLock<ControllerList>,
+    events_selected: AtomicBool,
+    callbacks: Mutex<Vec<(CallbackId, Arc<Callback>)>>,
+    next_callback_id: AtomicU64
 }
 
+type Callback = dyn Fn(&Instance, &Event) + Send + Sync;
+
+/// Identifies a callback registered with [`Instance::add_callback`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CallbackId(u64);
+
 impl Instance {
     /// Creates a new vibrant instance with a connection to the default X server.
     ///
     /// # Errors
     ///
-    /// Returns an error if a connection could not be established to the server.
+    /// Returns an error if a connection could not be established to the server, or if the server
+    /// failed to answer while looking for controllers.
     pub fn new() -> Result<Instance, Error> {
         let xcon = Display::from_display_name(None)?;
-        let controllers = controller::get_controllers(&xcon);
+        let controllers = controller::get_controllers(&xcon)?;
         Ok(Instance {
             xcon,
-            controllers
+            controllers: RwLock::new(controllers),
+            events_selected: AtomicBool::new(false),
+            callbacks: Mutex::new(Vec::new()),
+            next_callback_id: AtomicU64::new(0)
         })
     }
 
@@ -34,24 +74,296 @@ impl Instance {
     ///
     /// # Errors
     ///
-    /// Returns an error if a connection could not be established to the server.
+    /// Returns an error if a connection could not be established to the server, or if the server
+    /// failed to answer while looking for controllers.
     pub fn from_display_name(name: &CStr) -> Result<Instance, Error> {
         let xcon = Display::from_display_name(Some(name))?;
-        let controllers = controller::get_controllers(&xcon);
+        let controllers = controller::get_controllers(&xcon)?;
         Ok(Instance {
             xcon,
-            controllers
+            controllers: RwLock::new(controllers),
+            events_selected: AtomicBool::new(false),
+            callbacks: Mutex::new(Vec::new()),
+            next_callback_id: AtomicU64::new(0)
         })
     }
 
     /// Returns a list of controllers that correspond to displays that have a controllable backend.
-    pub fn controllers(&self) -> &[Box<dyn Controller>] {
-        &self.controllers
+    ///
+    /// The controllers stay usable even if the list gets replaced by [`Instance::refresh`].
+    pub fn controllers(&self) -> Vec<ControllerRef<'_>> {
+        self.controllers.read().unwrap().controllers.iter()
+            .map(|controller| ControllerRef::new(self, controller.clone()))
+            .collect()
+    }
+
+    /// Returns the controllers whose displays show the given point of the first X screen. There
+    /// is more than one if outputs are mirrored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the server failed to answer.
+    pub fn controller_at(&self, x: i32, y: i32) -> Result<Vec<ControllerRef<'_>>, Error> {
+        self.controllers_overlapping(0, x, y, 1, 1)
+    }
+
+    /// Returns the controllers whose displays show a part of the given window, the one showing
+    /// the largest part of it comes first.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the window does not exist or the server failed to answer.
+    pub fn controller_for_window(&self, window: u32) -> Result<Vec<ControllerRef<'_>>, Error> {
+        let (screen, x, y, width, height) = self.window_geometry(window)?;
+        self.controllers_overlapping(screen, x, y, width, height)
+    }
+
+    /// Returns the screen of a window and the rectangle it covers on it, border included.
+    pub(crate) fn window_geometry(&self, window: u32) -> Result<(usize, i32, i32, u32, u32), Error> {
+        let xcon = self.xcon.xcon();
+        let geometry = xcon.get_geometry(window)?.reply()?;
+        // the geometry is relative to the parent, we need it relative to the root
+        let position = xcon.translate_coordinates(window, geometry.root, 0, 0)?.reply()?;
+        let screen = xcon.setup().roots.iter()
+            .position(|screen| screen.root == geometry.root)
+            .unwrap_or(0);
+
+        let border = u32::from(geometry.border_width);
+        Ok((screen,
+            i32::from(position.dst_x) - border as i32,
+            i32::from(position.dst_y) - border as i32,
+            u32::from(geometry.width) + 2 * border,
+            u32::from(geometry.height) + 2 * border))
+    }
+
+    /// Returns the controllers overlapping the given rectangle, largest overlap first.
+    fn controllers_overlapping(&self, screen: usize, x: i32, y: i32, width: u32,
+                               height: u32) -> Result<Vec<ControllerRef<'_>>, Error> {
+        let mut overlapping = Vec::new();
+        for controller in self.controllers() {
+            let info = controller.output_info()?;
+            let overlap = info.overlap(x, y, width, height);
+            if info.screen == screen && info.crtc.is_some() && overlap > 0 {
+                overlapping.push((overlap, controller));
+            }
+        }
+
+        // stable, so equal overlaps keep the order of controllers()
+        overlapping.sort_by(|(a, _), (b, _)| b.cmp(a));
+        Ok(overlapping.into_iter().map(|(_, controller)| controller).collect())
+    }
+
+    /// Returns the saturation, brightness, color transform and gamma of every display, to put them back with
+    /// [`Instance::restore`] later.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the server failed to answer.
+    pub fn snapshot(&self) -> Result<DisplayState, Error> {
+        let controllers = self.controllers().iter()
+            .map(|controller| {
+                let matrix = match controller.get_color_transform() {
+                    Ok(matrix) => Some(matrix),
+                    Err(Error::Unsupported(_)) => None,
+                    Err(error) => return Err(error)
+                };
+                let brightness = match controller.get_brightness() {
+                    Ok(level) => Some(level),
+                    Err(Error::Unsupported(_)) => None,
+                    Err(error) => return Err(error)
+                };
+                Ok(ControllerState {
+                    name: controller.get_name().to_string(),
+                    identity: controller.identity()?,
+                    backend: controller.get_backend(),
+                    saturation: controller.get_saturation()?,
+                    brightness,
+                    matrix,
+                    gamma: controller.get_gamma()?
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(DisplayState {
+            controllers
+        })
+    }
+
+    /// Puts the displays back the way a [`DisplayState`] describes them. Displays are matched by
+    /// the identity of their monitor, so a monitor that moved to another port gets its own state
+    /// back. Displays that are not in the snapshot are left alone.
+    ///
+    /// Returns the states that no display was found for, e.g. because the monitor was unplugged.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the server failed to answer or rejected one of the values, the
+    /// displays matched before that are restored already.
+    pub fn restore<'s>(&self, state: &'s DisplayState) -> Result<Vec<&'s ControllerState>, Error> {
+        let controllers = self.controllers();
+        let current = controllers.iter()
+            .map(|controller| Ok((controller.get_name().to_string(), controller.identity()?)))
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let mut unmatched = Vec::new();
+        for (saved, pair) in state.controllers.iter()
+            .zip(snapshot::pair(&state.controllers, &current)) {
+            let controller = match pair {
+                Some(idx) => &controllers[idx],
+                None => {
+                    unmatched.push(saved);
+                    continue;
+                }
+            };
+
+            // first, the matrix is dimmed to the brightness it is set at
+            match saved.brightness.map(|level| controller.set_brightness(level)) {
+                Some(Ok(())) | Some(Err(Error::Unsupported(_))) | None => {}
+                Some(Err(error)) => return Err(error)
+            }
+            // a state taken through another backend may come with a matrix we can't set
+            match saved.matrix.map(|matrix| controller.set_color_transform(&matrix)) {
+                Some(Ok(())) => {}
+                Some(Err(Error::Unsupported(_))) | None => {
+                    controller.set_saturation(saved.saturation)?;
+                }
+                Some(Err(error)) => return Err(error)
+            }
+            if let Some(gamma) = &saved.gamma {
+                controller.set_gamma(gamma)?;
+            }
+        }
+
+        Ok(unmatched)
+    }
+
+    /// Looks for controllers again, e.g. after a display has been plugged in or removed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the server failed to answer while looking for controllers. The previous
+    /// list of controllers is kept in that case.
+    pub fn refresh(&self) -> Result<(), Error> {
+        let controllers = controller::get_controllers(&self.xcon)?;
+        // new NVIDIA displays have to be selected for events too
+        if self.events_selected.load(Ordering::SeqCst) {
+            event::select(&self.xcon, &controllers)?;
+        }
+        *self.controllers.write().unwrap() = controllers;
+        Ok(())
+    }
+
+    /// Starts reporting [`Event`]s for this instance. Until this is called no events are queued,
+    /// after it is called they have to be fetched with [`Instance::poll_event`] or
+    /// [`Instance::wait_event`], otherwise they pile up in memory.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the server refused to send us events.
+    pub fn select_events(&self) -> Result<(), Error> {
+        event::select(&self.xcon, &self.controllers.read().unwrap())?;
+        self.events_selected.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Returns the next queued event without blocking, or `None` if there is no event left.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the connection to the server broke.
+    pub fn poll_event(&self) -> Result<Option<Event>, Error> {
+        while let Some(event) = self.xcon.xcon().poll_for_event()? {
+            if let Some(event) = event::translate(&self.xcon, &self.controllers.read().unwrap(),
+                                                  event) {
+                return Ok(Some(event));
+            }
+        }
+
+        Ok(None)
     }
 
+    /// Blocks until the next event arrives and returns it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the connection to the server broke.
+    pub fn wait_event(&self) -> Result<Event, Error> {
+        loop {
+            let event = self.xcon.xcon().wait_for_event()?;
+            if let Some(event) = event::translate(&self.xcon, &self.controllers.read().unwrap(),
+                                                  event) {
+                return Ok(event);
+            }
+        }
+    }
 
-    /// Returns a pointer to the X display.
-    pub fn xcon(&self) -> *mut x11::xlib::Display {
+    /// Registers a callback that is invoked by [`Instance::dispatch_pending`] for every event.
+    /// Events have to be selected with [`Instance::select_events`] for it to be called.
+    pub fn add_callback<F>(&self, callback: F) -> CallbackId
+        where F: Fn(&Instance, &Event) + Send + Sync + 'static {
+        let id = CallbackId(self.next_callback_id.fetch_add(1, Ordering::SeqCst));
+        self.callbacks.lock().unwrap().push((id, Arc::new(callback)));
+        id
+    }
+
+    /// Unregisters a callback. Returns false if there was no callback with the given id.
+    pub fn remove_callback(&self, id: CallbackId) -> bool {
+        let mut callbacks = self.callbacks.lock().unwrap();
+        let len = callbacks.len();
+        callbacks.retain(|(callback_id, _)| *callback_id != id);
+        callbacks.len() != len
+    }
+
+    /// Hands every queued event to the registered callbacks without blocking and returns how
+    /// many events were dispatched.
+    ///
+    /// This is meant for main loops that watch the file descriptor of the connection, see the
+    /// [`AsRawFd`] implementation. Call it whenever the descriptor becomes readable, and once
+    /// before going to sleep, since events may already have been read off the socket while
+    /// waiting for a reply.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the connection to the server broke.
+    pub fn dispatch_pending(&self) -> Result<usize, Error> {
+        // requests that are still buffered could be what the server is going to answer
+        self.xcon.xcon().flush()?;
+
+        let mut dispatched = 0;
+        while let Some(event) = self.poll_event()? {
+            // callbacks may add or remove callbacks, so don't hold on to the lock
+            let callbacks: Vec<_> = self.callbacks.lock().unwrap().iter()
+                .map(|(_, callback)| callback.clone())
+                .collect();
+            for callback in callbacks {
+                callback(self, &event);
+            }
+            dispatched += 1;
+        }
+
+        Ok(dispatched)
+    }
+
+    pub(crate) fn display(&self) -> &Display {
+        &self.xcon
+    }
+
+    /// Returns the connection to the X server.
+    pub fn xcon(&self) -> &RustConnection {
         self.xcon.xcon()
     }
 }
+
+/// The file descriptor of the connection to the X server. It becomes readable when the server
+/// sent something, see [`Instance::dispatch_pending`].
+impl AsRawFd for Instance {
+    fn as_raw_fd(&self) -> RawFd {
+        self.xcon.xcon().stream().as_raw_fd()
+    }
+}
+
+impl AsFd for Instance {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.xcon.xcon().stream().as_fd()
+    }
+}
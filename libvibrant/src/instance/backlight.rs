@@ -0,0 +1,115 @@
+use crate::instance::xwrapper::Display;
+use crate::instance::property;
+use crate::instance::Error;
+use x11rb::protocol::randr::{ConnectionExt as _, QueryOutputPropertyReply};
+use x11rb::protocol::xproto::{Atom, AtomEnum};
+
+/// The raw values the backlight of an output takes, both ends included. They are whatever the
+/// kernel driver uses, often 0 to a few hundred or thousand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BacklightRange {
+    pub min: i32,
+    pub max: i32
+}
+
+impl BacklightRange {
+    /// Maps a raw value to [0.0, 1.0], 0.0 being the minimum of the range.
+    pub fn normalize(&self, value: i32) -> f64 {
+        if self.max <= self.min {
+            return 1.0;
+        }
+        let value = value.clamp(self.min, self.max);
+        (value - self.min) as f64 / (self.max - self.min) as f64
+    }
+
+    /// Maps a level in [0.0, 1.0] to the closest raw value. Input is clamped.
+    pub fn denormalize(&self, level: f64) -> i32 {
+        let level = level.clamp(0.0, 1.0);
+        let span = self.max as f64 - self.min as f64;
+        (self.min as f64 + (level * span).round()) as i32
+    }
+}
+
+/// The backlight property of an output, which is called `Backlight` by the modesetting and
+/// Intel drivers and `BACKLIGHT` by others.
+#[derive(Debug, Clone, Copy)]
+pub struct Backlight {
+    property: Atom,
+    range: BacklightRange
+}
+
+impl Backlight {
+    /// Returns the backlight described by the answer to a query of the property, None if the
+    /// property is not a range that can be set.
+    pub fn from_reply(property: Atom, reply: &QueryOutputPropertyReply) -> Option<Backlight> {
+        if !reply.range || reply.immutable || reply.valid_values.len() != 2 {
+            return None;
+        }
+        Some(Backlight {
+            property,
+            range: BacklightRange {
+                min: reply.valid_values[0],
+                max: reply.valid_values[1]
+            }
+        })
+    }
+
+    pub fn range(&self) -> BacklightRange {
+        self.range
+    }
+
+    /// Reads the raw value of the backlight of an output.
+    pub fn query(&self, display: &Display, output: u32) -> Result<i32, Error> {
+        let reply = display.xcon()
+            .randr_get_output_property(output, self.property, AtomEnum::INTEGER, 0, 1, false,
+                                       false)?
+            .reply()?;
+        if reply.type_ != u32::from(AtomEnum::INTEGER) || reply.format != 32 ||
+            reply.data.len() != 4 {
+            return Err(Error::BadProperty {
+                output,
+                property: "Backlight"
+            });
+        }
+        Ok(i32::from_ne_bytes([reply.data[0], reply.data[1], reply.data[2], reply.data[3]]))
+    }
+
+    /// Sets the raw value of the backlight of an output, clamped to the range.
+    pub fn apply(&self, display: &Display, output: u32, value: i32) -> Result<(), Error> {
+        let value = value.clamp(self.range.min, self.range.max);
+        property::change(display, output, self.property, AtomEnum::INTEGER, &[value as u32])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_range() {
+        let range = BacklightRange { min: 0, max: 937 };
+        assert_eq!(range.normalize(0), 0.0);
+        assert_eq!(range.normalize(937), 1.0);
+        assert_eq!(range.normalize(2000), 1.0);
+        assert_eq!(range.denormalize(0.5), 469);
+        assert_eq!(range.denormalize(-1.0), 0);
+
+        let offset = BacklightRange { min: 1, max: 11 };
+        assert_eq!(offset.denormalize(offset.normalize(6)), 6);
+        assert_eq!(offset.normalize(6), 0.5);
+
+        let reply = QueryOutputPropertyReply {
+            range: true,
+            valid_values: vec![0, 255],
+            ..Default::default()
+        };
+        let backlight = Backlight::from_reply(1, &reply).unwrap();
+        assert_eq!(backlight.range(), BacklightRange { min: 0, max: 255 });
+        let fixed = QueryOutputPropertyReply {
+            range: false,
+            ..reply
+        };
+        assert!(Backlight::from_reply(1, &fixed).is_none());
+    }
+}
@@ -1,112 +1,575 @@
 mod nvidia_controller;
 mod ctm_controller;
+mod tiled_controller;
 
-use crate::instance::xwrapper::{RROutput, Display};
-use x11::{xlib, xrandr};
-use libXNVCtrl_sys as nvctrl;
-use std::os::raw::{c_int, c_uchar};
-use std::slice::from_raw_parts;
-use std::ptr::null_mut;
+use crate::instance::xwrapper::{RROutput, Display, nvcontrol};
 use crate::instance::controller::nvidia_controller::NvidiaController;
 use crate::instance::controller::ctm_controller::CTMController;
-use std::ffi::CStr;
-use crate::instance::Instance;
+use crate::instance::controller::tiled_controller::TiledController;
+use crate::instance::{Instance, Error};
+use crate::guard::{SaturationGuard, TransformGuard};
+use crate::instance::backlight::{Backlight, BacklightRange};
+use crate::instance::gamma::{self, GammaRamp};
+use crate::instance::identity::{self, Identity};
+use crate::instance::output_info::{self, OutputInfo};
+use crate::instance::property::{self, BroadcastRgb, OutputProperty};
+use x11rb::connection::Connection;
+use x11rb::errors::{ConnectionError, ReplyError};
+use x11rb::protocol::randr::ConnectionExt as _;
+use x11rb::protocol::xproto::{AtomEnum, ConnectionExt as _};
 use std::fmt;
 use std::fmt::Formatter;
+use std::sync::Arc;
 
 const SATURATION_MIN: f64 = 0.0;
 const SATURATION_MAX: f64 = 4.0;
 
+/// A 3x3 color transform matrix, row by row. Every output color is the input color multiplied
+/// with it, so the rows produce red, green and blue.
+pub type ColorMatrix = [[f64; 3]; 3];
+
+/// Returns the color transform matrix that sets the given saturation, which is how the CTM
+/// backend implements saturation.
+pub(crate) fn saturation_matrix(saturation: f64) -> ColorMatrix {
+    let mut ctm_coeffs: ColorMatrix = [[0.0; 3]; 3];
+    let coeff = (1.0 -  saturation) / 3.0;
+    for (row, values) in ctm_coeffs.iter_mut().enumerate() {
+        for (column, val) in values.iter_mut().enumerate() {
+            if row == column {
+                *val = coeff + saturation;
+            }
+            else {
+                *val = coeff + 0.0;
+            }
+        }
+    }
+    ctm_coeffs
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ControllerBackend {
     XNVCtrl,
     CTM
 }
 
+/// The controllers found on a server, along with what is needed to match events to them.
+pub struct ControllerList {
+    pub controllers: Vec<Arc<dyn Controller>>,
+    /// (nvidia_id, xrandr_id) of every display that is controlled through XNVCtrl
+    pub nvidia_ids: Vec<(u16, u32)>
+}
+
 /// Returns a list of displays we can control on the given X server.
-pub fn get_controllers(display: &Display) -> Vec<Box<dyn Controller>> {
-    let outputs = RROutput::from_display(display);
-    let mut controllers = Vec::<Box<dyn Controller>>::with_capacity(outputs.len());
+pub fn get_controllers(display: &Display) -> Result<ControllerList, Error> {
+    let xcon = display.xcon();
+    let outputs = RROutput::from_display(display)?;
+    let mut controllers = Vec::<Arc<dyn Controller>>::with_capacity(outputs.len());
 
     // (nvidia_id, xrandr_id)
     let mut nvidia_ids = Vec::new();
-    if display.has_nvidia(){
+    if let Some(opcode) = display.nvcontrol_opcode() {
         //this will give us the id nvidia assigns to each display and its respective xrandr id
-        unsafe {
-            for i in 0..xlib::XScreenCount(display.xcon()) {
-                let mut ids: *mut c_int = null_mut();
-                // not really going to use this
-                let mut ids_len: c_int = 0;
-                // The way this call works is weird, ids_len will contain how many bytes there are
-                // in ids. The first element will of ids is going to contain how many elements are
-                // in ids. That's why we eventually override the value in ids_len
-                nvctrl::XNVCTRLQueryBinaryData(display.xcon(), i, 0,
-                                               nvctrl::NV_CTRL_BINARY_DATA_DISPLAYS_ENABLED_ON_XSCREEN,
-                                               &mut ids as *mut *mut c_int as *mut *mut c_uchar,
-                                               &mut ids_len as *mut _);
-                ids_len = *ids;
-                let ids = from_raw_parts(ids, ids_len as usize);
-                nvidia_ids.reserve(ids.len());
-                for id in ids {
-                    let mut xrandr_id = 0;
-                    nvctrl::XNVCTRLQueryTargetAttribute(display.xcon(),
-                                                        nvctrl::NV_CTRL_TARGET_TYPE_DISPLAY,
-                                                        *id, 0,
-                                                        nvctrl::NV_CTRL_DISPLAY_RANDR_OUTPUT_ID,
-                                                        &mut xrandr_id as *mut u64 as *mut i32);
-
-                    nvidia_ids.push((*id, xrandr_id));
+        for screen in 0..xcon.setup().roots.len() {
+            let reply = nvcontrol::query_binary_data(
+                xcon, opcode, nvcontrol::TARGET_TYPE_X_SCREEN, screen as u16, 0,
+                nvcontrol::BINARY_DATA_DISPLAYS_ENABLED_ON_XSCREEN)?.reply()?;
+            // this screen is not driven by an NVIDIA gpu
+            if reply.flags == 0 {
+                continue;
+            }
+            let ids = nvcontrol::parse_display_ids(&reply.data).map_err(ConnectionError::from)?;
+
+            let cookies = ids.iter()
+                .map(|id| {
+                    nvcontrol::query_target_attribute(xcon, opcode, nvcontrol::TARGET_TYPE_DISPLAY,
+                                                      *id, 0,
+                                                      nvcontrol::DISPLAY_RANDR_OUTPUT_ID)
+                        .map(|cookie| (*id, cookie))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            nvidia_ids.reserve(cookies.len());
+            for (id, cookie) in cookies {
+                let reply = cookie.reply()?;
+                if reply.flags != 0 {
+                    nvidia_ids.push((id, reply.value as u32));
                 }
             }
         }
     }
 
     //check which outputs have CTM
-    let prop_atom;
-    unsafe {
-        prop_atom = xlib::XInternAtom(display.xcon(),
-                                      CStr::from_bytes_with_nul_unchecked(b"CTM\0").as_ptr(), 1);
-    }
+    let prop_atom = display.ctm_atom();
 
-    'outer: for output in outputs {
+    // query every output up front so the CTM checks are pipelined, the order of outputs is kept
+    let mut candidates = Vec::with_capacity(outputs.len());
+    for output in outputs {
         // Check if this output can be controlled by XNVCtrl and add it as such if so
-        for (nvidia_id, xrandr_id) in &nvidia_ids {
-            if output.id() == *xrandr_id {
-                controllers.push(Box::new(NvidiaController::new(output, *nvidia_id)));
-                continue 'outer;
+        let nvidia_id = nvidia_ids.iter()
+            .find(|(_, xrandr_id)| output.id() == *xrandr_id)
+            .map(|(nvidia_id, _)| *nvidia_id);
+        // If not then check if it can be controlled by CTM
+        let ctm_cookie = if nvidia_id.is_none() && prop_atom != u32::from(AtomEnum::NONE) {
+            Some(xcon.randr_query_output_property(output.id(), prop_atom)?)
+        }
+        else {
+            None
+        };
+
+        // laptop panels can also have a backlight, whichever the backend
+        let backlight_cookies = display.backlight_atoms().iter()
+            .map(|atom| xcon.randr_query_output_property(output.id(), *atom)
+                .map(|cookie| (*atom, cookie)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        candidates.push((output, nvidia_id, ctm_cookie, backlight_cookies));
+    }
+
+    let mut controlled_nvidia_ids = Vec::new();
+    for (output, nvidia_id, ctm_cookie, backlight_cookies) in candidates {
+        let mut backlight = None;
+        for (atom, cookie) in backlight_cookies {
+            match cookie.reply() {
+                Ok(reply) => backlight = backlight.or_else(|| Backlight::from_reply(atom, &reply)),
+                Err(ReplyError::X11Error(_)) => {},
+                Err(err) => return Err(err.into())
             }
         }
-        // If not then check if it can be controlled by CTM
-        if prop_atom != 0 {
-            let property_info;
-            unsafe {
-                property_info = xrandr::XRRQueryOutputProperty(display.xcon(), output.id(),
-                                                               prop_atom);
+
+        if let (Some(opcode), Some(nvidia_id)) = (display.nvcontrol_opcode(), nvidia_id) {
+            controlled_nvidia_ids.push((nvidia_id, output.id()));
+            controllers.push(Arc::new(NvidiaController::new(output, opcode, nvidia_id,
+                                                            backlight)));
+        }
+        else if let Some(cookie) = ctm_cookie {
+            match cookie.reply() {
+                Ok(_) => controllers.push(Arc::new(CTMController::new(
+                    output, prop_atom, display.brightness_atom(), backlight))),
+                // the output does not have the property
+                Err(ReplyError::X11Error(_)) => {},
+                Err(err) => return Err(err.into())
             }
+        }
+    }
 
-            if !property_info.is_null() {
-                unsafe {
-                    xlib::XFree(property_info as *mut _);
-                }
+    // Tiled displays show up as several outputs that together make up one monitor
+    if display.has_randr(1, 5) {
+        controllers = group_tiles(display, controllers)?;
+    }
+
+    Ok(ControllerList {
+        controllers,
+        nvidia_ids: controlled_nvidia_ids
+    })
+}
 
-                controllers.push(Box::new(CTMController::new(output, prop_atom)));
+/// Replaces the controllers of outputs that are tiles of the same RandR monitor with a single
+/// controller that sets all of them.
+fn group_tiles(display: &Display, mut controllers: Vec<Arc<dyn Controller>>)
+    -> Result<Vec<Arc<dyn Controller>>, Error> {
+    let xcon = display.xcon();
+    let cookies = xcon.setup().roots.iter()
+        .map(|screen| xcon.randr_get_monitors(screen.root, true))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    for cookie in cookies {
+        for monitor in cookie.reply()?.monitors {
+            let tiles: Vec<usize> = controllers.iter().enumerate()
+                .filter(|(_, controller)| monitor.outputs.contains(&controller.get_output_id()))
+                .map(|(idx, _)| idx)
+                .collect();
+            if tiles.len() < 2 {
+                continue;
             }
+
+            let name = xcon.get_atom_name(monitor.name)?.reply()?.name;
+            // remove from the back so the indices stay valid, the monitor takes the place of its
+            // first tile
+            let mut tile_controllers: Vec<_> = tiles.iter().rev()
+                .map(|idx| controllers.remove(*idx))
+                .collect();
+            tile_controllers.reverse();
+            controllers.insert(tiles[0],
+                               Arc::new(TiledController::new(
+                                   String::from_utf8_lossy(&name).into_owned(),
+                                   tile_controllers)));
         }
     }
 
-    controllers
+    Ok(controllers)
 }
 
 /// Generic interface for dealing with any controller type.
-pub trait Controller {
+pub trait Controller: Send + Sync {
     /// Returns the saturation of the screen. In the range of [0.0, 4.0].
-    fn get_saturation(&self, instance: &Instance) -> f64;
+    fn get_saturation(&self, display: &Display) -> Result<f64, Error>;
     /// Sets the screen saturation. Input is clamped to the range of [0.0, 4.0].
-    fn set_saturation(&self, instance: &Instance, saturation: f64);
+    fn set_saturation(&self, display: &Display, saturation: f64) -> Result<(), Error>;
+    /// Returns the saturation the display ends up with when the given one is set, for backends
+    /// that only store it in steps.
+    fn quantize_saturation(&self, saturation: f64) -> f64 {
+        saturation.clamp(SATURATION_MIN, SATURATION_MAX)
+    }
+    /// Returns the brightness of the screen in the range of [0.0, 1.0], if the backend can dim.
+    fn get_brightness(&self, display: &Display) -> Result<f64, Error>;
+    /// Dims the screen. Input is clamped to the range of [0.0, 1.0].
+    fn set_brightness(&self, display: &Display, level: f64) -> Result<(), Error>;
+    /// Returns the color transform matrix, if the backend supports arbitrary matrices.
+    fn get_color_transform(&self, display: &Display) -> Result<ColorMatrix, Error>;
+    /// Replaces the color transform matrix, if the backend supports arbitrary matrices.
+    fn set_color_transform(&self, display: &Display, matrix: &ColorMatrix) -> Result<(), Error>;
 
     /// Returns the name of the screen.
     fn get_name(&self) -> &str;
+    /// Returns the id of the RandR output this controller is attached to.
+    fn get_output_id(&self) -> u32;
+    /// Returns the ids of every RandR output this controller sets, more than one for tiled
+    /// monitors.
+    fn get_output_ids(&self) -> Vec<u32> {
+        vec![self.get_output_id()]
+    }
     /// Returns the backend used for this controller.
     fn get_backend(&self) -> ControllerBackend;
+    /// Returns the backlights of the outputs along with their ids, none if no output has one.
+    /// Every backlight has a range of its own.
+    fn get_backlights(&self) -> Vec<(u32, Backlight)>;
+}
+
+/// A handle to a display that has a controllable backend. It borrows the [`Instance`] it came
+/// from, so it can only ever talk to the server that display belongs to.
+#[derive(Clone)]
+pub struct ControllerRef<'a> {
+    instance: &'a Instance,
+    controller: Arc<dyn Controller>
+}
+
+impl<'a> ControllerRef<'a> {
+    pub(crate) fn new(instance: &'a Instance, controller: Arc<dyn Controller>) -> ControllerRef<'a> {
+        ControllerRef {
+            instance,
+            controller
+        }
+    }
+
+    /// Returns the instance this controller belongs to.
+    pub fn instance(&self) -> &'a Instance {
+        self.instance
+    }
+
+    #[cfg(feature = "async")]
+    pub(crate) fn inner(&self) -> &Arc<dyn Controller> {
+        &self.controller
+    }
+
+    /// Returns the saturation of the screen. In the range of [0.0, 4.0].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the server failed to answer.
+    pub fn get_saturation(&self) -> Result<f64, Error> {
+        self.controller.get_saturation(self.instance.display())
+    }
+
+    /// Sets the screen saturation. Input is clamped to the range of [0.0, 4.0]. With the CTM
+    /// backend the screen stays as dimmed as [`ControllerRef::set_brightness`] left it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the server rejected the new saturation.
+    pub fn set_saturation(&self, saturation: f64) -> Result<(), Error> {
+        self.controller.set_saturation(self.instance.display(), saturation)
+    }
+
+    /// Returns the brightness of the screen in the range of [0.0, 1.0]. It is kept on the display
+    /// next to the color transform matrix, so every client sees the one last set with
+    /// [`ControllerRef::set_brightness`]. It is never guessed from the matrix: once another
+    /// program replaced the matrix, the screen is at full brightness, even if that matrix dims.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Unsupported`] for the XNVCtrl backend, or an error if the server failed
+    /// to answer.
+    pub fn get_brightness(&self) -> Result<f64, Error> {
+        self.controller.get_brightness(self.instance.display())
+    }
+
+    /// Dims the screen by scaling its color transform matrix, which works for monitors without
+    /// a backlight and leaves gamma ramps alone. Input is clamped to the range of [0.0, 1.0], 1.0
+    /// is full brightness. Saturation and color transforms set afterwards stay dimmed, and
+    /// [`ControllerRef::get_color_transform`] returns the matrix before it was dimmed.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Unsupported`] for the XNVCtrl backend, or an error if the server rejected
+    /// the new matrix.
+    pub fn set_brightness(&self, level: f64) -> Result<(), Error> {
+        self.controller.set_brightness(self.instance.display(), level)
+    }
+
+    /// Returns the raw values the backlight of the display takes, None if it has no backlight
+    /// that can be set through RandR. Usually only laptop panels have one. For a tiled monitor
+    /// it is the range of the first tile, the others may have ranges of their own.
+    pub fn backlight_range(&self) -> Option<BacklightRange> {
+        self.controller.get_backlights().first().map(|(_, backlight)| backlight.range())
+    }
+
+    /// Returns the backlight of the display in the range of [0.0, 1.0], 0.0 being the lowest
+    /// value of its range. Depending on the driver that may turn the backlight off. For a tiled
+    /// monitor it is the average of its tiles.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Unsupported`] if the display has no backlight, or an error if the server
+    /// failed to answer.
+    pub fn get_backlight(&self) -> Result<f64, Error> {
+        let backlights = self.controller.get_backlights();
+        if backlights.is_empty() {
+            return Err(Error::Unsupported("backlight control"));
+        }
+        let mut sum = 0.0;
+        for (output, backlight) in &backlights {
+            sum += backlight.range().normalize(backlight.query(self.instance.display(), *output)?);
+        }
+        Ok(sum / backlights.len() as f64)
+    }
+
+    /// Sets the backlight of the display. Input is clamped to the range of [0.0, 1.0] and
+    /// rounded to the closest value the backlight takes, see
+    /// [`ControllerRef::backlight_range`]. Every tile of a tiled monitor gets the same level
+    /// within its own range.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Unsupported`] if the display has no backlight, or an error if the server
+    /// rejected the new value.
+    pub fn set_backlight(&self, level: f64) -> Result<(), Error> {
+        let backlights = self.controller.get_backlights();
+        if backlights.is_empty() {
+            return Err(Error::Unsupported("backlight control"));
+        }
+        for (output, backlight) in backlights {
+            backlight.apply(self.instance.display(), output, backlight.range().denormalize(level))?;
+        }
+        Ok(())
+    }
+
+    /// Returns the saturation the display ends up with when the given one is set. The XNVCtrl
+    /// backend only stores it in steps of about 0.003.
+    pub fn quantize_saturation(&self, saturation: f64) -> f64 {
+        self.controller.quantize_saturation(saturation)
+    }
+
+    /// Sets the screen saturation until the returned guard is dropped, which puts the current
+    /// saturation back.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the server failed to answer or rejected the new saturation.
+    pub fn set_saturation_scoped(&self, saturation: f64) -> Result<SaturationGuard<'a>, Error> {
+        let previous = self.get_saturation()?;
+        let guard = SaturationGuard::new(self.clone(), previous);
+        match self.set_saturation(saturation) {
+            Ok(()) => Ok(guard),
+            Err(error) => {
+                guard.keep();
+                Err(error)
+            }
+        }
+    }
+
+    /// Returns the color transform matrix of the screen. Saturation is one such matrix, so this
+    /// reflects changes made through [`ControllerRef::set_saturation`] as well. The dimming of
+    /// [`ControllerRef::set_brightness`] is not part of it.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Unsupported`] for the XNVCtrl backend, which only offers digital
+    /// vibrance, or an error if the server failed to answer.
+    pub fn get_color_transform(&self) -> Result<ColorMatrix, Error> {
+        self.controller.get_color_transform(self.instance.display())
+    }
+
+    /// Replaces the color transform matrix of the screen, e.g. to correct colors or to apply a
+    /// color temperature. The screen stays as dimmed as [`ControllerRef::set_brightness`] left
+    /// it.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Unsupported`] for the XNVCtrl backend, or an error if the server rejected
+    /// the matrix.
+    pub fn set_color_transform(&self, matrix: &ColorMatrix) -> Result<(), Error> {
+        self.controller.set_color_transform(self.instance.display(), matrix)
+    }
+
+    /// Replaces the color transform matrix of the screen until the returned guard is dropped,
+    /// which puts the current matrix back.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Unsupported`] for the XNVCtrl backend, or an error if the server failed
+    /// to answer or rejected the matrix.
+    pub fn set_color_transform_scoped(&self,
+                                      matrix: &ColorMatrix) -> Result<TransformGuard<'a>, Error> {
+        let previous = self.get_color_transform()?;
+        let guard = TransformGuard::new(self.clone(), previous);
+        match self.set_color_transform(matrix) {
+            Ok(()) => Ok(guard),
+            Err(error) => {
+                guard.keep();
+                Err(error)
+            }
+        }
+    }
+
+    /// Returns the gamma ramps of the display, None if it is turned off. For tiled monitors
+    /// these are the ramps of the first tile.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the server failed to answer.
+    pub fn get_gamma(&self) -> Result<Option<GammaRamp>, Error> {
+        let display = self.instance.display();
+        match gamma::crtc_of(display, self.get_output_id())? {
+            Some(crtc) => Ok(Some(gamma::query(display, crtc)?)),
+            None => Ok(None)
+        }
+    }
+
+    /// Replaces the gamma ramps of the display, of every tile for tiled monitors. Tiles that are
+    /// turned off are left alone.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the server rejected the ramps, e.g. because their size differs from
+    /// the one of the display.
+    pub fn set_gamma(&self, ramp: &GammaRamp) -> Result<(), Error> {
+        let display = self.instance.display();
+        for output in self.get_output_ids() {
+            if let Some(crtc) = gamma::crtc_of(display, output)? {
+                gamma::apply(display, crtc, ramp)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the name of the screen.
+    pub fn get_name(&self) -> &str {
+        self.controller.get_name()
+    }
+
+    /// Returns the id of the RandR output this controller is attached to. This is the id that
+    /// [`Event`](crate::Event)s refer to. For tiled monitors it is the id of the first tile.
+    pub fn get_output_id(&self) -> u32 {
+        self.controller.get_output_id()
+    }
+
+    /// Returns the ids of every RandR output this controller sets. Tiled monitors are made out
+    /// of several outputs which are always set together.
+    pub fn get_output_ids(&self) -> Vec<u32> {
+        self.controller.get_output_ids()
+    }
+
+    /// Returns the backend used for this controller.
+    pub fn get_backend(&self) -> ControllerBackend {
+        self.controller.get_backend()
+    }
+
+    /// Returns where and how the display of this controller is currently shown. For tiled
+    /// monitors the geometry spans all tiles.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the server failed to answer.
+    pub fn output_info(&self) -> Result<OutputInfo, Error> {
+        let tiles = self.get_output_ids().into_iter()
+            .map(|output| output_info::query(self.instance.display(), output))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(OutputInfo::merge(tiles).expect("a controller always has an output"))
+    }
+
+    /// Returns every RandR property of the output, with its value and the values it accepts.
+    /// For tiled monitors these are the properties of the first tile.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the server failed to answer.
+    pub fn properties(&self) -> Result<Vec<OutputProperty>, Error> {
+        property::list(self.instance.display(), self.get_output_id())
+    }
+
+    /// Sets a property that holds a number, of every tile for tiled monitors. The number is
+    /// written with the type the property has, `INTEGER` or `CARDINAL`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::MissingProperty`] if the output has no such property,
+    /// [`Error::InvalidPropertyValue`] if it does not accept the value or holds no numbers, or an
+    /// error if the server rejected it.
+    pub fn set_property_integer(&self, name: &str, value: i32) -> Result<(), Error> {
+        for output in self.get_output_ids() {
+            property::set_integer(self.instance.display(), output, name, value)?;
+        }
+        Ok(())
+    }
+
+    /// Sets a property that holds one of a list of names, like `TearFree`, of every tile for
+    /// tiled monitors.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::MissingProperty`] if the output has no such property,
+    /// [`Error::InvalidPropertyValue`] if the name is not one it accepts, or an error if the
+    /// server rejected it.
+    pub fn set_property_name(&self, name: &str, value: &str) -> Result<(), Error> {
+        for output in self.get_output_ids() {
+            property::set_name(self.instance.display(), output, name, value)?;
+        }
+        Ok(())
+    }
+
+    /// Sets the range of RGB values sent to the monitor.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::MissingProperty`] if the driver has no `Broadcast RGB` property, or an
+    /// error if the server rejected the value.
+    pub fn set_broadcast_rgb(&self, range: BroadcastRgb) -> Result<(), Error> {
+        self.set_property_name("Broadcast RGB", range.name())
+    }
+
+    /// Limits the bits per color the monitor is driven with, which takes effect with the next
+    /// mode set.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::MissingProperty`] if the driver has no `max bpc` property,
+    /// [`Error::InvalidPropertyValue`] if the depth is out of its range, or an error if the server
+    /// rejected the value.
+    pub fn set_max_bpc(&self, bits: u8) -> Result<(), Error> {
+        self.set_property_integer("max bpc", i32::from(bits))
+    }
+
+    /// Sets the colorimetry the monitor is told the picture is in, e.g. `BT2020_RGB`. The names
+    /// differ between drivers, the accepted ones are listed by [`ControllerRef::properties`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::MissingProperty`] if the driver has no `Colorspace` property,
+    /// [`Error::InvalidPropertyValue`] if it does not know the name, or an error if the server
+    /// rejected it.
+    pub fn set_colorspace(&self, colorspace: &str) -> Result<(), Error> {
+        self.set_property_name("Colorspace", colorspace)
+    }
+
+    /// Returns which monitor is attached, read from its EDID. For tiled monitors this is the
+    /// identity of the first tile. None if the monitor does not provide an EDID.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the server failed to answer.
+    pub fn identity(&self) -> Result<Option<Identity>, Error> {
+        identity::query(self.instance.display(), self.get_output_id())
+    }
 }
 
 impl fmt::Display for ControllerBackend {
@@ -1,131 +1,277 @@
-use crate::instance::xwrapper::RROutput;
-use crate::instance::controller::{Controller, SATURATION_MIN, SATURATION_MAX, ControllerBackend};
-use std::os::raw::{c_long, c_ulong};
-use std::slice::from_raw_parts;
-use x11::{xlib, xrandr};
-use std::mem::size_of;
-use x11::xlib::XA_INTEGER;
-use crate::instance::Instance;
+use crate::instance::xwrapper::{RROutput, Display};
+use crate::instance::controller::{Controller, ColorMatrix, SATURATION_MIN, SATURATION_MAX,
+                                  ControllerBackend, saturation_matrix};
+use crate::instance::backlight::Backlight;
+use crate::instance::property;
+use crate::instance::Error;
+use x11rb::protocol::randr::ConnectionExt as _;
+use x11rb::protocol::xproto::{Atom, AtomEnum};
+
+/// How many 32 bit items a CTM takes on the wire.
+const CTM_ITEMS: usize = 18;
+
+/// How many 32 bit items the brightness record takes, see [`record`].
+const RECORD_ITEMS: usize = 2 + 2 * CTM_ITEMS;
 
 pub struct CTMController {
     output: RROutput,
-    ctm_prop: xlib::Atom,
-    name: String
+    ctm_prop: Atom,
+    /// Where the brightness is kept, see [`record`].
+    brightness_prop: Atom,
+    name: String,
+    backlight: Option<Backlight>
 }
 
 impl CTMController {
-    pub fn new(output: RROutput, ctm_prop: xlib::Atom) -> CTMController {
+    pub fn new(output: RROutput, ctm_prop: Atom, brightness_prop: Atom,
+               backlight: Option<Backlight>) -> CTMController {
         CTMController{
             name: output.name(),
             output,
-            ctm_prop
+            ctm_prop,
+            brightness_prop,
+            backlight
         }
     }
 }
 
-impl Controller for CTMController {
-    fn get_saturation(&self, instance: &Instance) -> f64 {
-        let xcon = instance.xcon();
-        //get the actual color matrix
-        let mut ctm: [u64; 9] = [0; 9];
-        unsafe {
-            let mut actual_type = 0;
-            let mut actual_format = 0;
-            let mut item_count = 0;
-            let mut bytes_after: c_ulong = 0;
-            let mut data_ptr: *const c_long = std::ptr::null();
-            xrandr::XRRGetOutputProperty(xcon, self.output.id(), self.ctm_prop, 0,
-                                         size_of::<c_long>() as c_long * 18, 0, 0, XA_INTEGER,
-                                         &mut actual_type as *mut _, &mut actual_format as *mut _,
-                                         &mut item_count as *mut _, &mut bytes_after as *mut _,
-                                         &mut data_ptr as *mut _ as *mut _);
-            if actual_type == XA_INTEGER && item_count == 18u64 {
-                let data_ptr = from_raw_parts(data_ptr, 18);
-                //see the set_saturation function for why this translation is needed
-                for i in (0..18).step_by(2) {
-                    ctm[i/2] = (data_ptr[i+1] as u64) << 32 | (data_ptr[i] as u64);
-                }
-            }
+impl CTMController {
+    /// Reads the matrix of the output without the dimming, along with the brightness.
+    fn read(&self, display: &Display) -> Result<(ColorMatrix, f64), Error> {
+        let xcon = display.xcon();
+        // both at once, the record is only of use along with the matrix it describes
+        let ctm = xcon.randr_get_output_property(self.output.id(), self.ctm_prop,
+                                                 AtomEnum::INTEGER, 0, CTM_ITEMS as u32, false,
+                                                 false)?;
+        let record = xcon.randr_get_output_property(self.output.id(), self.brightness_prop,
+                                                    AtomEnum::INTEGER, 0, RECORD_ITEMS as u32,
+                                                    false, false)?;
+        let ctm = ctm.reply()?;
+        let record = record.reply()?;
+        if ctm.type_ != u32::from(AtomEnum::INTEGER) || ctm.format != 32 ||
+            ctm.num_items != CTM_ITEMS as u32 || ctm.data.len() != CTM_ITEMS * 4 {
+            return Err(Error::BadProperty {
+                output: self.output.id(),
+                property: "CTM"
+            });
+        }
+
+        let record = if record.type_ == u32::from(AtomEnum::INTEGER) && record.format == 32 {
+            to_items(&record.data)
+        }
+        else {
+            Vec::new()
         };
+        Ok(read_back(&to_items(&ctm.data), &record))
+    }
+
+    /// Replaces the matrix of the output with one dimmed to the brightness, and records both.
+    fn write(&self, display: &Display, coeffs: &ColorMatrix, level: f64) -> Result<(), Error> {
+        let (ctm, record) = written(coeffs, level);
+        property::change(display, self.output.id(), self.ctm_prop, AtomEnum::INTEGER, &ctm)?;
+        property::change(display, self.output.id(), self.brightness_prop, AtomEnum::INTEGER,
+                         &record)
+    }
+}
+
+/// Splits the data of a property with a format of 32.
+fn to_items(data: &[u8]) -> Vec<u32> {
+    data.chunks_exact(4)
+        .map(|item| u32::from_ne_bytes([item[0], item[1], item[2], item[3]]))
+        .collect()
+}
+
+/// Turns a matrix into the items of a CTM.
+fn encode(coeffs: &ColorMatrix) -> Vec<u32> {
+    let mut ctm: [u64; 9] = [0; 9];
+    //translate the coeffs into a CTM
+    for i in 0..9 {
+        let coeff = coeffs[i / 3][i % 3];
+        if coeff < 0.0 {
+            ctm[i] = (-coeff * (1_u64 << 32) as f64) as u64;
+            ctm[i] |= 1_u64 << 63;
+        }
+        else {
+            ctm[i] = (coeff * (1_u64 << 32) as f64) as u64;
+        }
+    }
+
+    /* The format for CTM is supposed to be a 3x3 matrix of type S31.32, libdrm, and the kernel
+     * correctly use uint64_t in their code to represent a S31.32 number. The RandR property
+     * however is made out of 32 bit items, so on the wire the matrix is an array of 18 values
+     * where every number is split into its low and high half, in that order.
+     */
+    let mut items = Vec::with_capacity(CTM_ITEMS);
+    for value in ctm.iter() {
+        items.push(*value as u32);
+        items.push((*value >> 32) as u32);
+    }
+    items
+}
 
-        //translate the matrix into the coeffs
-        let mut coeffs: [f64; 9] = [0.0; 9];
-        for i in 0..9 {
-            //we need to clear the sign bit if we want to convert it into a floating point
-            let ctm_num = ctm[i] & !((1 as u64) << 63);
-            let mut coeff = (ctm_num as f64)/f64::powi(2.0, 32);
-            //recover original sign
-            if (ctm[i] & ((1 as u64) << 63)) != 0 {
-                coeff *= -1.0;
-            }
-
-            coeffs[i] = coeff;
+/// Turns the items of a CTM back into a matrix, row by row.
+fn decode(items: &[u32]) -> ColorMatrix {
+    let mut ctm: [u64; 9] = [0; 9];
+    //see encode for why this translation is needed
+    for i in (0..CTM_ITEMS).step_by(2) {
+        ctm[i/2] = (items[i+1] as u64) << 32 | (items[i] as u64);
+    }
+
+    //translate the matrix into the coeffs
+    let mut coeffs: ColorMatrix = [[0.0; 3]; 3];
+    for i in 0..9 {
+        //we need to clear the sign bit if we want to convert it into a floating point
+        let ctm_num = ctm[i] & !(1_u64 << 63);
+        let mut coeff = (ctm_num as f64)/f64::powi(2.0, 32);
+        //recover original sign
+        if (ctm[i] & (1_u64 << 63)) != 0 {
+            coeff *= -1.0;
         }
 
-        coeffs[0] - coeffs[1]
+        coeffs[i / 3][i % 3] = coeff;
+    }
+
+    coeffs
+}
+
+/// Multiplies every coefficient of a matrix.
+fn scale(coeffs: &ColorMatrix, factor: f64) -> ColorMatrix {
+    let mut scaled = *coeffs;
+    for value in scaled.iter_mut().flatten() {
+        *value *= factor;
     }
+    scaled
+}
+
+/// Returns the record of a brightness, which is kept in the `_VIBRANT_BRIGHTNESS` property of
+/// the output so every client sees it: the bits of the level as low and high half, the matrix
+/// before it was dimmed and the matrix that was written. A black screen still knows what it
+/// showed that way.
+fn record(level: f64, coeffs: &[u32], ctm: &[u32]) -> Vec<u32> {
+    let bits = level.to_bits();
+    let mut record = Vec::with_capacity(RECORD_ITEMS);
+    record.push(bits as u32);
+    record.push((bits >> 32) as u32);
+    record.extend_from_slice(coeffs);
+    record.extend_from_slice(ctm);
+    record
+}
+
+/// Returns the items of the CTM to write for a matrix dimmed to a brightness, and its record.
+fn written(coeffs: &ColorMatrix, level: f64) -> (Vec<u32>, Vec<u32>) {
+    let ctm = encode(&scale(coeffs, level));
+    let record = record(level, &encode(coeffs), &ctm);
+    (ctm, record)
+}
 
-    fn set_saturation(&self, instance: &Instance, mut saturation: f64) {
-        let xcon = instance.xcon();
+/// Returns the matrix without the dimming and the brightness, given the CTM of an output and its
+/// record. Nothing is guessed from the matrix: if the record is missing or describes another
+/// matrix, something else wrote the CTM and it is taken as it is, at full brightness, even if it
+/// darkens every color.
+fn read_back(ctm: &[u32], record: &[u32]) -> (ColorMatrix, f64) {
+    if record.len() == RECORD_ITEMS && record[2 + CTM_ITEMS..] == *ctm {
+        let level = f64::from_bits((record[1] as u64) << 32 | (record[0] as u64));
+        (decode(&record[2..2 + CTM_ITEMS]), level)
+    }
+    else {
+        (decode(ctm), 1.0)
+    }
+}
+
+impl Controller for CTMController {
+    fn get_saturation(&self, display: &Display) -> Result<f64, Error> {
+        let (coeffs, _) = self.read(display)?;
+        Ok(coeffs[0][0] - coeffs[0][1])
+    }
+
+    fn set_saturation(&self, display: &Display, mut saturation: f64) -> Result<(), Error> {
         saturation = f64::max(saturation, SATURATION_MIN);
         saturation = f64::min(saturation, SATURATION_MAX);
+        let (_, level) = self.read(display)?;
+        self.write(display, &saturation_matrix(saturation), level)
+    }
 
-        let mut ctm_coeffs: [f64; 9] = [0.0; 9];
-        let coeff = (1.0 -  saturation) / 3.0;
-        for (idx, val) in ctm_coeffs.iter_mut().enumerate() {
-            if idx % 4 == 0 {
-                *val = coeff + saturation;
-            }
-            else {
-                *val = coeff + 0.0;
-            }
-        }
+    fn get_brightness(&self, display: &Display) -> Result<f64, Error> {
+        Ok(self.read(display)?.1)
+    }
 
-        let mut ctm: [u64; 9] = [0; 9];
-        //translate the coeffs into a CTM
-        for i in 0..9 {
-            if ctm_coeffs[i] < 0.0 {
-                ctm[i] = (-ctm_coeffs[i] * ((1 as u64) << 32) as f64) as u64;
-                ctm[i] |= (1 as u64) << 63;
-            }
-            else {
-                ctm[i] = (ctm_coeffs[i] * ((1 as u64) << 32) as f64) as u64;
-            }
-        }
+    fn set_brightness(&self, display: &Display, level: f64) -> Result<(), Error> {
+        let (coeffs, _) = self.read(display)?;
+        self.write(display, &coeffs, level.clamp(0.0, 1.0))
+    }
 
-        /* The format for CTM is supposed to be a 3x3 matrix of type S31.32, libdrm, and the kernel
-         * correctly use uint64_t in their code to represent a S31.32 number. X11/Xrandr however,
-         * in all their infinite wisdom decided that their CTM should be of type long, and thus used
-         * an array of 18 long values. This is because according to the C standard long is at least
-         * 32 bits long. What they did not consider is that long is 64 bits on some systems. This
-         * means that the matrix on those systems essentially has the type S63.64. To deal with
-         * this we have to translate our matrix from holding u64, to holding whatever the system
-         * defines as the size for C's long type.
-         *
-         * Welcome to hell.
-         */
-        let mut padded_ctm: [c_long; 18] = [0; 18];
-        unsafe {
-            let ctm: &[i32] = from_raw_parts(ctm.as_ptr() as *const _, 18);
-            for i in 0..18 {
-                padded_ctm[i] = ctm[i] as c_long;
-            }
-        }
+    fn get_color_transform(&self, display: &Display) -> Result<ColorMatrix, Error> {
+        Ok(self.read(display)?.0)
+    }
 
-        // Now that we have our CTM we can actually set the value
-        unsafe {
-            xrandr::XRRChangeOutputProperty(xcon, self.output.id(), self.ctm_prop,
-                                            XA_INTEGER, 32, xlib::PropModeReplace,
-                                            padded_ctm.as_ptr() as *const _, 18);
-            xlib::XSync(xcon, 0);
-        }
+    fn set_color_transform(&self, display: &Display, matrix: &ColorMatrix) -> Result<(), Error> {
+        let (_, level) = self.read(display)?;
+        self.write(display, matrix, level)
     }
 
     fn get_name(&self) -> &str {
         &self.name
     }
 
+    fn get_output_id(&self) -> u32 {
+        self.output.id()
+    }
+
     fn get_backend(&self) -> ControllerBackend {
         ControllerBackend::CTM
     }
+
+    fn get_backlights(&self) -> Vec<(u32, Backlight)> {
+        self.backlight.map(|backlight| (self.output.id(), backlight)).into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color;
+
+    fn assert_close(matrix: &ColorMatrix, expected: &ColorMatrix) {
+        for (value, expected) in matrix.iter().flatten().zip(expected.iter().flatten()) {
+            assert!((value - expected).abs() < 1e-9, "{:?} != {:?}", matrix, expected);
+        }
+    }
+
+    #[test]
+    fn stays_dimmed() {
+        let saturated = saturation_matrix(2.0);
+        let (ctm, record) = written(&saturated, 0.4);
+        assert_close(&decode(&ctm), &scale(&saturated, 0.4));
+        let (coeffs, level) = read_back(&ctm, &record);
+        assert_close(&coeffs, &saturated);
+        assert_eq!(level, 0.4);
+
+        // what set_saturation and set_color_transform write afterwards
+        let warm = color::temperature(3000.0);
+        for coeffs in [saturation_matrix(3.0), warm] {
+            let (ctm, record) = written(&coeffs, level);
+            assert_close(&decode(&ctm), &scale(&coeffs, 0.4));
+            assert_eq!(read_back(&ctm, &record).1, 0.4);
+        }
+
+        // a black screen still knows what it showed
+        let (ctm, record) = written(&warm, 0.0);
+        assert_eq!(decode(&ctm), [[0.0; 3]; 3]);
+        let (coeffs, level) = read_back(&ctm, &record);
+        assert_close(&coeffs, &warm);
+        assert_eq!(level, 0.0);
+    }
+
+    #[test]
+    fn keeps_matrices_of_others() {
+        // rows summing to less than 1 are no brightness of ours
+        let dark = color::brightness(0.8);
+        let ctm = encode(&dark);
+        assert_eq!(read_back(&ctm, &[]), (decode(&ctm), 1.0));
+        assert_close(&read_back(&ctm, &[]).0, &dark);
+
+        // a record of a matrix that was replaced since
+        let (_, stale) = written(&saturation_matrix(2.0), 0.5);
+        assert_eq!(read_back(&ctm, &stale), (decode(&ctm), 1.0));
+    }
 }
@@ -1,70 +1,127 @@
-use std::os::raw::c_int;
-use crate::instance::xwrapper::RROutput;
-use crate::instance::controller::{Controller, SATURATION_MIN, SATURATION_MAX, ControllerBackend};
-use libXNVCtrl_sys as nvctrl;
-use crate::instance::Instance;
+use crate::instance::xwrapper::{RROutput, Display, nvcontrol};
+use crate::instance::controller::{Controller, ColorMatrix, SATURATION_MIN, SATURATION_MAX,
+                                  ControllerBackend};
+use crate::instance::backlight::Backlight;
+use crate::instance::Error;
 
 pub struct NvidiaController {
-    _output: RROutput,
-    nvidia_id: c_int,
-    name: String
+    output: RROutput,
+    opcode: u8,
+    nvidia_id: u16,
+    name: String,
+    backlight: Option<Backlight>
 }
 
 impl NvidiaController {
-    pub fn new(output: RROutput, nvidia_id: c_int) -> NvidiaController {
+    pub fn new(output: RROutput, opcode: u8, nvidia_id: u16,
+               backlight: Option<Backlight>) -> NvidiaController {
         NvidiaController {
             name: output.name(),
-            _output: output,
-            nvidia_id
+            output,
+            opcode,
+            nvidia_id,
+            backlight
         }
     }
 }
 
+/// Converts a saturation in [0.0, 4.0] to digital vibrance, which goes from -1024 to 1023 with
+/// 0 being the normal saturation.
+fn to_vibrance(saturation: f64) -> i32 {
+    //is saturation roughly in [0.0, 1.0]
+    if saturation <= 1.0 + f64::EPSILON {
+        (saturation * 1024.0 - 1024.0) as i32
+    } else {
+        ((saturation * 1023.0 - 1023.0) / 3.0) as i32
+    }
+}
+
+fn from_vibrance(nv_saturation: i32) -> f64 {
+    if nv_saturation < 0 {
+        (nv_saturation+1024) as f64/1024.0
+    }
+    else{
+        (nv_saturation*3+1023) as f64/1023.0
+    }
+}
+
 impl Controller for NvidiaController {
-    fn get_saturation(&self, instance: &Instance) -> f64 {
-        let xcon = instance.xcon();
-        let mut nv_saturation = 0;
-        unsafe {
-            nvctrl::XNVCTRLQueryTargetAttribute(xcon,
-                                                nvctrl::NV_CTRL_TARGET_TYPE_DISPLAY, self.nvidia_id,
-                                                0, nvctrl::NV_CTRL_DIGITAL_VIBRANCE,
-                                                &mut nv_saturation as *mut _);
-        };
-
-        if nv_saturation < 0 {
-            (nv_saturation+1024) as f64/1024.0
-        }
-        else{
-            (nv_saturation*3+1023) as f64/1023.0
+    fn get_saturation(&self, display: &Display) -> Result<f64, Error> {
+        let xcon = display.xcon();
+        let reply = nvcontrol::query_target_attribute(xcon, self.opcode,
+                                                      nvcontrol::TARGET_TYPE_DISPLAY,
+                                                      self.nvidia_id, 0,
+                                                      nvcontrol::DIGITAL_VIBRANCE)?
+            .reply()?;
+        // the display went away or the driver stopped offering vibrance for it
+        if reply.flags == 0 {
+            return Err(Error::Unsupported("digital vibrance"));
         }
+        Ok(from_vibrance(reply.value))
     }
 
-    fn set_saturation(&self, instance: &Instance, mut saturation: f64) {
-        let xcon = instance.xcon();
-        let nv_saturation;
+    fn set_saturation(&self, display: &Display, mut saturation: f64) -> Result<(), Error> {
+        let xcon = display.xcon();
 
         saturation = f64::max(saturation, SATURATION_MIN);
         saturation = f64::min(saturation, SATURATION_MAX);
 
-        //is saturation roughly in [0.0, 1.0]
-        if saturation >= 0.0 && saturation <= 1.0 + f64::EPSILON {
-            nv_saturation = (saturation * 1024.0 - 1024.0) as i32;
-        } else {
-            nv_saturation = ((saturation * 1023.0 - 1023.0) / 3.0) as i32;
-        }
+        nvcontrol::set_target_attribute(xcon, self.opcode, nvcontrol::TARGET_TYPE_DISPLAY,
+                                        self.nvidia_id, 0, nvcontrol::DIGITAL_VIBRANCE,
+                                        to_vibrance(saturation))?
+            .check()?;
+        Ok(())
+    }
 
-        unsafe {
-            nvctrl::XNVCTRLSetTargetAttribute(xcon, nvctrl::NV_CTRL_TARGET_TYPE_DISPLAY,
-                                              self.nvidia_id, 0, nvctrl::NV_CTRL_DIGITAL_VIBRANCE,
-                                              nv_saturation);
-        }
+    fn quantize_saturation(&self, saturation: f64) -> f64 {
+        from_vibrance(to_vibrance(saturation.clamp(SATURATION_MIN, SATURATION_MAX)))
+    }
+
+    fn get_brightness(&self, _: &Display) -> Result<f64, Error> {
+        Err(Error::Unsupported("brightness"))
+    }
+
+    fn set_brightness(&self, _: &Display, _: f64) -> Result<(), Error> {
+        Err(Error::Unsupported("brightness"))
+    }
+
+    fn get_color_transform(&self, _: &Display) -> Result<ColorMatrix, Error> {
+        Err(Error::Unsupported("color transform matrices"))
+    }
+
+    fn set_color_transform(&self, _: &Display, _: &ColorMatrix) -> Result<(), Error> {
+        Err(Error::Unsupported("color transform matrices"))
     }
 
     fn get_name(&self) -> &str {
         &self.name
     }
 
+    fn get_output_id(&self) -> u32 {
+        self.output.id()
+    }
+
     fn get_backend(&self) -> ControllerBackend {
         ControllerBackend::XNVCtrl
     }
+
+    fn get_backlights(&self) -> Vec<(u32, Backlight)> {
+        self.backlight.map(|backlight| (self.output.id(), backlight)).into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_vibrance() {
+        assert_eq!(to_vibrance(0.0), -1024);
+        assert_eq!(to_vibrance(1.0), 0);
+        assert_eq!(to_vibrance(4.0), 1023);
+        assert_eq!(from_vibrance(to_vibrance(2.5)), 2556.0 / 1023.0);
+        // steps above 1.0 are three times as large as below
+        assert_eq!(from_vibrance(to_vibrance(1.002)), 1.0);
+        assert_eq!(from_vibrance(to_vibrance(0.998)), 1022.0 / 1024.0);
+    }
 }
@@ -0,0 +1,82 @@
+use crate::instance::xwrapper::Display;
+use crate::instance::controller::{Controller, ColorMatrix, ControllerBackend};
+use crate::instance::backlight::Backlight;
+use crate::instance::Error;
+use std::sync::Arc;
+
+/// A monitor that is made out of several outputs, like tiled 5K and 8K displays. Every tile is
+/// always set to the same value so the monitor never ends up half adjusted.
+pub struct TiledController {
+    name: String,
+    tiles: Vec<Arc<dyn Controller>>
+}
+
+impl TiledController {
+    /// Creates a controller for the given tiles, there has to be at least one of them.
+    pub fn new(name: String, tiles: Vec<Arc<dyn Controller>>) -> TiledController {
+        assert!(!tiles.is_empty());
+        TiledController {
+            name,
+            tiles
+        }
+    }
+}
+
+impl Controller for TiledController {
+    fn get_saturation(&self, display: &Display) -> Result<f64, Error> {
+        self.tiles[0].get_saturation(display)
+    }
+
+    fn set_saturation(&self, display: &Display, saturation: f64) -> Result<(), Error> {
+        for tile in &self.tiles {
+            tile.set_saturation(display, saturation)?;
+        }
+        Ok(())
+    }
+
+    fn quantize_saturation(&self, saturation: f64) -> f64 {
+        self.tiles[0].quantize_saturation(saturation)
+    }
+
+    fn get_brightness(&self, display: &Display) -> Result<f64, Error> {
+        self.tiles[0].get_brightness(display)
+    }
+
+    fn set_brightness(&self, display: &Display, level: f64) -> Result<(), Error> {
+        for tile in &self.tiles {
+            tile.set_brightness(display, level)?;
+        }
+        Ok(())
+    }
+
+    fn get_color_transform(&self, display: &Display) -> Result<ColorMatrix, Error> {
+        self.tiles[0].get_color_transform(display)
+    }
+
+    fn set_color_transform(&self, display: &Display, matrix: &ColorMatrix) -> Result<(), Error> {
+        for tile in &self.tiles {
+            tile.set_color_transform(display, matrix)?;
+        }
+        Ok(())
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn get_output_id(&self) -> u32 {
+        self.tiles[0].get_output_id()
+    }
+
+    fn get_output_ids(&self) -> Vec<u32> {
+        self.tiles.iter().flat_map(|tile| tile.get_output_ids()).collect()
+    }
+
+    fn get_backend(&self) -> ControllerBackend {
+        self.tiles[0].get_backend()
+    }
+
+    fn get_backlights(&self) -> Vec<(u32, Backlight)> {
+        self.tiles.iter().flat_map(|tile| tile.get_backlights()).collect()
+    }
+}
@@ -1,7 +1,31 @@
 use thiserror::Error;
+use x11rb::errors::{ConnectionError, ReplyError};
 
 #[derive(Error, Debug)]
 pub enum Error {
     #[error("Failed to open connection to display named: {0}")]
     OpenDisplay(String),
+    #[error("Failed to communicate with the X server: {0}")]
+    Connection(#[from] ConnectionError),
+    #[error("The X server rejected a request: {0}")]
+    Reply(#[from] ReplyError),
+    #[error("The display does not support {0}")]
+    Unsupported(&'static str),
+    #[error("The {property} property of output {output} is malformed")]
+    BadProperty {
+        output: u32,
+        property: &'static str
+    },
+    #[error("Output {output} has no {property} property")]
+    MissingProperty {
+        output: u32,
+        property: String
+    },
+    #[error("{value} is not a valid value of the {property} property")]
+    InvalidPropertyValue {
+        property: String,
+        value: String
+    },
+    #[error("The controller of {0} belongs to another instance")]
+    ForeignController(String),
 }
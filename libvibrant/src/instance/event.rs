@@ -0,0 +1,111 @@
+use crate::instance::controller::ControllerList;
+use crate::instance::xwrapper::{Display, nvcontrol};
+use crate::instance::Error;
+use x11rb::connection::Connection;
+use x11rb::protocol::randr::{self, ConnectionExt as _};
+use x11rb::x11_utils::TryParse;
+
+/// A change on the X server that concerns displays or their controllers. Events are only reported
+/// after calling [`Instance::select_events`](crate::Instance::select_events).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// The layout of a screen changed, e.g. a resolution, position or rotation.
+    ScreenChanged,
+    /// The output with the given id was connected. Call [`Instance::refresh`](crate::Instance::refresh)
+    /// to get a controller for it.
+    OutputConnected(u32),
+    /// The output with the given id was disconnected.
+    OutputDisconnected(u32),
+    /// The saturation of the output with the given id was changed, by us or by another client.
+    SaturationChanged(u32),
+    /// Any other property of an output changed.
+    OutputPropertyChanged {
+        output: u32,
+        property: u32
+    },
+    /// A property of a window changed. Only reported for windows whose property changes were
+    /// selected through the connection, as [`Profiles`](crate::profiles::Profiles) does for the
+    /// root windows and the focused window.
+    WindowPropertyChanged {
+        window: u32,
+        property: u32
+    },
+    /// A window was moved or resized. Only reported for windows whose structure changes were
+    /// selected through the connection.
+    WindowGeometryChanged(u32)
+}
+
+/// Asks the server to send us the events of every screen and NVIDIA display.
+pub fn select(display: &Display, list: &ControllerList) -> Result<(), Error> {
+    let xcon = display.xcon();
+    let mask = randr::NotifyMask::SCREEN_CHANGE | randr::NotifyMask::OUTPUT_CHANGE |
+        randr::NotifyMask::OUTPUT_PROPERTY;
+    for screen in &xcon.setup().roots {
+        xcon.randr_select_input(screen.root, mask)?.check()?;
+    }
+
+    if let Some(opcode) = display.nvcontrol_opcode() {
+        for (nvidia_id, _) in &list.nvidia_ids {
+            nvcontrol::select_target_notify(xcon, opcode, nvcontrol::TARGET_TYPE_DISPLAY,
+                                            *nvidia_id,
+                                            nvcontrol::TARGET_ATTRIBUTE_CHANGED_EVENT, true)?
+                .check()?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Translates an event from the server, returns None for events we do not care about.
+pub fn translate(display: &Display, list: &ControllerList,
+                 event: x11rb::protocol::Event) -> Option<Event> {
+    use x11rb::protocol::Event as XEvent;
+
+    match event {
+        XEvent::RandrScreenChangeNotify(_) => Some(Event::ScreenChanged),
+        XEvent::RandrNotify(event) if event.sub_code == randr::Notify::OUTPUT_CHANGE => {
+            let change = event.u.as_oc();
+            if change.connection == randr::Connection::CONNECTED {
+                Some(Event::OutputConnected(change.output))
+            }
+            else {
+                Some(Event::OutputDisconnected(change.output))
+            }
+        }
+        XEvent::RandrNotify(event) if event.sub_code == randr::Notify::OUTPUT_PROPERTY => {
+            let change = event.u.as_op();
+            if change.atom == display.ctm_atom() {
+                Some(Event::SaturationChanged(change.output))
+            }
+            else {
+                Some(Event::OutputPropertyChanged {
+                    output: change.output,
+                    property: change.atom
+                })
+            }
+        }
+        XEvent::PropertyNotify(event) => Some(Event::WindowPropertyChanged {
+            window: event.window,
+            property: event.atom
+        }),
+        XEvent::ConfigureNotify(event) => Some(Event::WindowGeometryChanged(event.window)),
+        XEvent::Unknown(bytes) if display.nvcontrol_opcode().is_some() => {
+            // the top bit only marks events that were sent by another client
+            let code = bytes.first()? & 0x7f;
+            if code != display.nvcontrol_first_event() + nvcontrol::TARGET_ATTRIBUTE_CHANGED_EVENT {
+                return None;
+            }
+
+            let (event, _) = nvcontrol::TargetAttributeChangedEvent::try_parse(&bytes).ok()?;
+            if event.target_type != nvcontrol::TARGET_TYPE_DISPLAY ||
+                event.attribute != nvcontrol::DIGITAL_VIBRANCE {
+                return None;
+            }
+
+            list.nvidia_ids.iter()
+                .find(|(nvidia_id, _)| *nvidia_id == event.target_id)
+                .map(|(_, output)| Event::SaturationChanged(*output))
+        }
+        _ => None
+    }
+}
@@ -0,0 +1,72 @@
+use crate::instance::xwrapper::Display;
+use crate::instance::Error;
+use x11rb::protocol::randr::ConnectionExt as _;
+use x11rb::CURRENT_TIME;
+
+/// The gamma ramps of a CRTC, one value per step for each channel. Every channel has the same
+/// number of steps, which is fixed by the driver.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GammaRamp {
+    pub red: Vec<u16>,
+    pub green: Vec<u16>,
+    pub blue: Vec<u16>
+}
+
+impl GammaRamp {
+    /// Returns a ramp with the given number of steps that leaves colors unchanged.
+    pub fn linear(size: usize) -> GammaRamp {
+        let ramp: Vec<u16> = (0..size)
+            .map(|step| (step * 0xffff / size.saturating_sub(1).max(1)) as u16)
+            .collect();
+        GammaRamp {
+            red: ramp.clone(),
+            green: ramp.clone(),
+            blue: ramp
+        }
+    }
+
+    /// Returns the number of steps of the ramps.
+    pub fn size(&self) -> usize {
+        self.red.len()
+    }
+}
+
+/// Returns the CRTC driving an output, None if the output is turned off.
+pub fn crtc_of(display: &Display, output: u32) -> Result<Option<u32>, Error> {
+    let crtc = display.xcon().randr_get_output_info(output, CURRENT_TIME)?.reply()?.crtc;
+    Ok(if crtc == 0 { None } else { Some(crtc) })
+}
+
+/// Reads the gamma ramps of a CRTC.
+pub fn query(display: &Display, crtc: u32) -> Result<GammaRamp, Error> {
+    let reply = display.xcon().randr_get_crtc_gamma(crtc)?.reply()?;
+    Ok(GammaRamp {
+        red: reply.red,
+        green: reply.green,
+        blue: reply.blue
+    })
+}
+
+/// Replaces the gamma ramps of a CRTC. The server rejects ramps whose size differs from the one
+/// of the CRTC.
+pub fn apply(display: &Display, crtc: u32, ramp: &GammaRamp) -> Result<(), Error> {
+    if ramp.green.len() != ramp.size() || ramp.blue.len() != ramp.size() {
+        return Err(Error::Unsupported("gamma ramps of different sizes"));
+    }
+    display.xcon().randr_set_crtc_gamma(crtc, &ramp.red, &ramp.green, &ramp.blue)?.check()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_linear_ramp() {
+        let ramp = GammaRamp::linear(256);
+        assert_eq!(ramp.size(), 256);
+        assert_eq!((ramp.red[0], ramp.red[255]), (0, 0xffff));
+        assert_eq!(ramp.green[128], 0x8080);
+    }
+}
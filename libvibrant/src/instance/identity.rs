@@ -0,0 +1,136 @@
+use crate::instance::xwrapper::Display;
+use crate::instance::Error;
+use std::fmt;
+use x11rb::protocol::randr::ConnectionExt as _;
+use x11rb::protocol::xproto::AtomEnum;
+
+/// Which physical monitor is attached to an output, read from its EDID. Unlike the name of the
+/// output it stays the same when the monitor is plugged into another port.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Identity {
+    /// The three letter PNP id of the manufacturer, e.g. `DEL`.
+    pub manufacturer: String,
+    pub product: u16,
+    /// The numeric serial number, 0 if the monitor does not report one.
+    pub serial: u32,
+    /// The model name from the display descriptors, e.g. `DELL U2720Q`.
+    pub name: Option<String>,
+    /// The serial number from the display descriptors, more reliable than the numeric one.
+    pub serial_number: Option<String>
+}
+
+/// Formats as `DEL-A0F2-ABC123`, the serial is left out if the monitor does not report one.
+impl fmt::Display for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{:04X}", self.manufacturer, self.product)?;
+        match &self.serial_number {
+            Some(serial) => write!(f, "-{}", serial),
+            None if self.serial != 0 => write!(f, "-{}", self.serial),
+            None => Ok(())
+        }
+    }
+}
+
+/// Reads the text of a display descriptor, which ends with a newline and is padded with spaces.
+fn descriptor_text(text: &[u8]) -> Option<String> {
+    let end = text.iter().position(|c| *c == b'\n').unwrap_or(text.len());
+    let text = String::from_utf8_lossy(&text[..end]).trim().to_string();
+    if text.is_empty() {
+        None
+    }
+    else {
+        Some(text)
+    }
+}
+
+/// Parses the base block of an EDID, returns None if it is not a valid one.
+pub fn parse(edid: &[u8]) -> Option<Identity> {
+    const HEADER: [u8; 8] = [0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00];
+    let edid = edid.get(..128)?;
+    if edid[..8] != HEADER || edid.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0 {
+        return None;
+    }
+
+    // three letters of 5 bits each, 1 is A
+    let id = u16::from_be_bytes([edid[8], edid[9]]);
+    let manufacturer = [(id >> 10) & 0x1f, (id >> 5) & 0x1f, id & 0x1f].iter()
+        .map(|letter| char::from(b'@' + *letter as u8))
+        .collect();
+
+    let mut identity = Identity {
+        manufacturer,
+        product: u16::from_le_bytes([edid[10], edid[11]]),
+        serial: u32::from_le_bytes([edid[12], edid[13], edid[14], edid[15]]),
+        name: None,
+        serial_number: None
+    };
+
+    // four 18 byte descriptors, the ones starting with zeros are display descriptors
+    for descriptor in edid[54..126].chunks_exact(18) {
+        if descriptor[..3] != [0, 0, 0] {
+            continue;
+        }
+        match descriptor[3] {
+            0xfc => identity.name = descriptor_text(&descriptor[5..]),
+            0xff => identity.serial_number = descriptor_text(&descriptor[5..]),
+            _ => {}
+        }
+    }
+
+    Some(identity)
+}
+
+/// Reads the identity of the monitor attached to an output, None if it has no usable EDID.
+pub fn query(display: &Display, output: u32) -> Result<Option<Identity>, Error> {
+    if display.edid_atom() == u32::from(AtomEnum::NONE) {
+        return Ok(None);
+    }
+
+    let reply = display.xcon()
+        .randr_get_output_property(output, display.edid_atom(), AtomEnum::INTEGER, 0, 32, false,
+                                   false)?
+        .reply()?;
+    if reply.format != 8 {
+        return Ok(None);
+    }
+
+    Ok(parse(&reply.data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edid() -> Vec<u8> {
+        let mut edid = vec![0; 128];
+        edid[..8].copy_from_slice(&[0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00]);
+        // DEL
+        edid[8..10].copy_from_slice(&[0x10, 0xac]);
+        edid[10..12].copy_from_slice(&0xa0f2_u16.to_le_bytes());
+        edid[12..16].copy_from_slice(&1234_u32.to_le_bytes());
+        edid[72..77].copy_from_slice(&[0, 0, 0, 0xfc, 0]);
+        edid[77..90].copy_from_slice(b"DELL U2720Q\n ");
+        edid[90..95].copy_from_slice(&[0, 0, 0, 0xff, 0]);
+        edid[95..108].copy_from_slice(b"ABC123\n      ");
+        let sum = edid.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+        edid[127] = 0u8.wrapping_sub(sum);
+        edid
+    }
+
+    #[test]
+    fn parses_edid() {
+        let identity = parse(&edid()).unwrap();
+        assert_eq!(identity.manufacturer, "DEL");
+        assert_eq!(identity.product, 0xa0f2);
+        assert_eq!(identity.serial, 1234);
+        assert_eq!(identity.name.as_deref(), Some("DELL U2720Q"));
+        assert_eq!(identity.serial_number.as_deref(), Some("ABC123"));
+        assert_eq!(identity.to_string(), "DEL-A0F2-ABC123");
+
+        let mut corrupted = edid();
+        corrupted[20] ^= 1;
+        assert!(parse(&corrupted).is_none());
+        assert!(parse(&edid()[..127]).is_none());
+    }
+}
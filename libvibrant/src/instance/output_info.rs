@@ -0,0 +1,228 @@
+use crate::instance::xwrapper::Display;
+use crate::instance::Error;
+use x11rb::connection::Connection;
+use x11rb::protocol::randr::{self, ConnectionExt as _};
+use x11rb::CURRENT_TIME;
+
+/// How the picture of an output is rotated, counter clockwise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rotation {
+    Normal,
+    Left,
+    Inverted,
+    Right
+}
+
+/// A display mode, the resolution and timings an output is driven with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModeInfo {
+    pub id: u32,
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    /// In Hz.
+    pub refresh_rate: f64
+}
+
+/// Where and how the output of a controller is shown, as reported by RandR.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputInfo {
+    /// The number of the X screen the output belongs to.
+    pub screen: usize,
+    /// The CRTC driving the output, None if the output is connected but turned off.
+    pub crtc: Option<u32>,
+    /// Position of the top left corner on the screen, in pixels.
+    pub x: i32,
+    pub y: i32,
+    /// Size on the screen after rotation, in pixels.
+    pub width: u32,
+    pub height: u32,
+    pub mode: Option<ModeInfo>,
+    pub rotation: Rotation,
+    /// Physical size of the display, 0 if the display does not report it.
+    pub mm_width: u32,
+    pub mm_height: u32,
+    /// If this is the primary output of its screen.
+    pub primary: bool
+}
+
+impl OutputInfo {
+    /// Combines the tiles of a tiled monitor into a single output spanning all of them.
+    pub(crate) fn merge(tiles: Vec<OutputInfo>) -> Option<OutputInfo> {
+        let mut tiles = tiles.into_iter();
+        let mut info = tiles.next()?;
+        for tile in tiles {
+            let right = (info.x + info.width as i32).max(tile.x + tile.width as i32);
+            let bottom = (info.y + info.height as i32).max(tile.y + tile.height as i32);
+            // tiles report the physical size of their own part of the panel
+            if tile.x >= info.x + info.width as i32 {
+                info.mm_width += tile.mm_width;
+            }
+            if tile.y >= info.y + info.height as i32 {
+                info.mm_height += tile.mm_height;
+            }
+
+            info.x = info.x.min(tile.x);
+            info.y = info.y.min(tile.y);
+            info.width = (right - info.x) as u32;
+            info.height = (bottom - info.y) as u32;
+            info.primary |= tile.primary;
+        }
+
+        Some(info)
+    }
+
+    /// Returns how many pixels of the given rectangle on the same screen are shown on this output.
+    pub(crate) fn overlap(&self, x: i32, y: i32, width: u32, height: u32) -> u64 {
+        let left = self.x.max(x);
+        let top = self.y.max(y);
+        let right = (self.x + self.width as i32).min(x + width as i32);
+        let bottom = (self.y + self.height as i32).min(y + height as i32);
+
+        if right <= left || bottom <= top {
+            0
+        }
+        else {
+            (right - left) as u64 * (bottom - top) as u64
+        }
+    }
+}
+
+/// Returns the refresh rate of a mode in Hz.
+fn refresh_rate(mode: &randr::ModeInfo) -> f64 {
+    let mut vtotal = f64::from(mode.vtotal);
+    if mode.mode_flags & randr::ModeFlag::DOUBLE_SCAN != 0u32.into() {
+        vtotal *= 2.0;
+    }
+    if mode.mode_flags & randr::ModeFlag::INTERLACE != 0u32.into() {
+        vtotal /= 2.0;
+    }
+
+    let dots = f64::from(mode.htotal) * vtotal;
+    if dots == 0.0 {
+        0.0
+    }
+    else {
+        f64::from(mode.dot_clock) / dots
+    }
+}
+
+/// Queries the current state of an output.
+pub fn query(display: &Display, output: u32) -> Result<OutputInfo, Error> {
+    let xcon = display.xcon();
+    let roots: Vec<_> = xcon.setup().roots.iter().map(|screen| screen.root).collect();
+
+    let output_info = xcon.randr_get_output_info(output, CURRENT_TIME)?;
+    // we don't know which screen the output belongs to, so ask all of them
+    let primaries = roots.iter()
+        .map(|root| xcon.randr_get_output_primary(*root))
+        .collect::<Result<Vec<_>, _>>()?;
+    let resources = roots.iter()
+        .map(|root| xcon.randr_get_screen_resources_current(*root))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let output_info = output_info.reply()?;
+    let mut primary = false;
+    for cookie in primaries {
+        primary |= cookie.reply()?.output == output;
+    }
+    let resources = resources.into_iter()
+        .map(|cookie| cookie.reply())
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut info = OutputInfo {
+        screen: resources.iter().position(|resources| resources.outputs.contains(&output))
+            .unwrap_or(0),
+        crtc: None,
+        x: 0,
+        y: 0,
+        width: 0,
+        height: 0,
+        mode: None,
+        rotation: Rotation::Normal,
+        mm_width: output_info.mm_width,
+        mm_height: output_info.mm_height,
+        primary
+    };
+
+    if output_info.crtc == 0 {
+        return Ok(info);
+    }
+
+    let crtc_info = xcon.randr_get_crtc_info(output_info.crtc, CURRENT_TIME)?.reply()?;
+    info.crtc = Some(output_info.crtc);
+    info.x = i32::from(crtc_info.x);
+    info.y = i32::from(crtc_info.y);
+    info.width = u32::from(crtc_info.width);
+    info.height = u32::from(crtc_info.height);
+    info.rotation = if crtc_info.rotation & randr::Rotation::ROTATE90 != 0u16.into() {
+        Rotation::Left
+    }
+    else if crtc_info.rotation & randr::Rotation::ROTATE180 != 0u16.into() {
+        Rotation::Inverted
+    }
+    else if crtc_info.rotation & randr::Rotation::ROTATE270 != 0u16.into() {
+        Rotation::Right
+    }
+    else {
+        Rotation::Normal
+    };
+
+    let resources = &resources[info.screen];
+    // the names of all modes are stored back to back
+    let mut name_start = 0;
+    for mode in &resources.modes {
+        let name_end = name_start + usize::from(mode.name_len);
+        if mode.id == crtc_info.mode {
+            let name = resources.names.get(name_start..name_end).unwrap_or_default();
+            info.mode = Some(ModeInfo {
+                id: mode.id,
+                name: String::from_utf8_lossy(name).into_owned(),
+                width: u32::from(mode.width),
+                height: u32::from(mode.height),
+                refresh_rate: refresh_rate(mode)
+            });
+        }
+        name_start = name_end;
+    }
+
+    Ok(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tile(x: i32, primary: bool) -> OutputInfo {
+        OutputInfo {
+            screen: 0,
+            crtc: Some(1),
+            x,
+            y: 0,
+            width: 2560,
+            height: 2880,
+            mode: None,
+            rotation: Rotation::Normal,
+            mm_width: 300,
+            mm_height: 340,
+            primary
+        }
+    }
+
+    #[test]
+    fn merges_side_by_side_tiles() {
+        let info = OutputInfo::merge(vec![tile(1920, false), tile(4480, true)]).unwrap();
+        assert_eq!((info.x, info.y, info.width, info.height), (1920, 0, 5120, 2880));
+        assert_eq!((info.mm_width, info.mm_height), (600, 340));
+        assert!(info.primary);
+        assert!(OutputInfo::merge(Vec::new()).is_none());
+    }
+
+    #[test]
+    fn computes_overlap() {
+        let info = tile(0, false);
+        assert_eq!(info.overlap(2460, 100, 200, 100), 100 * 100);
+        assert_eq!(info.overlap(2560, 0, 100, 100), 0);
+        assert_eq!(info.overlap(10, 10, 1, 1), 1);
+    }
+}
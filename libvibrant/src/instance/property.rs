@@ -0,0 +1,339 @@
+use crate::instance::xwrapper::Display;
+use crate::instance::Error;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use x11rb::protocol::randr::{ConnectionExt as _, QueryOutputPropertyReply};
+use x11rb::protocol::xproto::{Atom, AtomEnum, ConnectionExt as _, PropMode};
+
+/// How much of a property is read, in 32 bit units. Enough for EDIDs with every extension block.
+const MAX_LENGTH: u32 = 0x4000;
+
+/// The value of an output property, decoded according to its type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PropertyValue {
+    /// `INTEGER` and `CARDINAL` properties, e.g. `max bpc`.
+    Integers(Vec<i64>),
+    /// `ATOM` properties, which hold the names of the atoms, e.g. `Broadcast RGB`.
+    Atoms(Vec<String>),
+    /// `STRING` properties.
+    Text(String),
+    /// Properties of any other type, as the bytes the server sent.
+    Other {
+        type_name: String,
+        format: u8,
+        data: Vec<u8>
+    }
+}
+
+/// Which values an output property accepts.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidValues {
+    /// Whatever the driver makes of it, like `CTM` or `EDID`.
+    Any,
+    /// Numbers between min and max, both included.
+    Range {
+        min: i32,
+        max: i32
+    },
+    /// One of a list of numbers.
+    Integers(Vec<i32>),
+    /// One of a list of names, for `ATOM` properties.
+    Names(Vec<String>)
+}
+
+/// A property of a RandR output, as shown by `xrandr --prop`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputProperty {
+    pub name: String,
+    /// The name of the type the value has, like `INTEGER`, `CARDINAL` or `ATOM`.
+    pub type_name: String,
+    pub value: PropertyValue,
+    pub valid_values: ValidValues,
+    /// If clients are not allowed to change it, like `vrr_capable`.
+    pub immutable: bool,
+    /// If changes only take effect with the next mode set.
+    pub pending: bool
+}
+
+/// The range of RGB values sent to a monitor, the `Broadcast RGB` property of Intel and AMD
+/// drivers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BroadcastRgb {
+    /// Limited for TV modes and full for everything else.
+    Automatic,
+    /// 0 to 255.
+    Full,
+    /// 16 to 235, what TVs expect.
+    Limited
+}
+
+impl BroadcastRgb {
+    /// Returns the name of the value as the drivers spell it.
+    pub fn name(&self) -> &'static str {
+        match self {
+            BroadcastRgb::Automatic => "Automatic",
+            BroadcastRgb::Full => "Full",
+            BroadcastRgb::Limited => "Limited 16:235"
+        }
+    }
+}
+
+/// Turns the items of an `INTEGER` or `CARDINAL` property into numbers, `INTEGER` ones being
+/// signed.
+fn decode_integers(format: u8, data: &[u8], signed: bool) -> Vec<i64> {
+    match format {
+        8 => data.iter()
+            .map(|item| if signed { *item as i8 as i64 } else { *item as i64 })
+            .collect(),
+        16 => data.chunks_exact(2)
+            .map(|item| u16::from_ne_bytes([item[0], item[1]]))
+            .map(|item| if signed { item as i16 as i64 } else { item as i64 })
+            .collect(),
+        _ => data.chunks_exact(4)
+            .map(|item| u32::from_ne_bytes([item[0], item[1], item[2], item[3]]))
+            .map(|item| if signed { item as i32 as i64 } else { item as i64 })
+            .collect()
+    }
+}
+
+/// Splits a list of 32 bit items.
+fn decode_atoms(data: &[u8]) -> Vec<Atom> {
+    data.chunks_exact(4)
+        .map(|item| u32::from_ne_bytes([item[0], item[1], item[2], item[3]]))
+        .collect()
+}
+
+/// Looks up the names of atoms, asking the server only for the ones it has not seen yet.
+struct AtomNames<'a> {
+    display: &'a Display,
+    names: HashMap<Atom, String>
+}
+
+impl<'a> AtomNames<'a> {
+    fn new(display: &'a Display) -> AtomNames<'a> {
+        AtomNames {
+            display,
+            names: HashMap::new()
+        }
+    }
+
+    /// Fetches the names of the given atoms with one round trip.
+    fn fetch(&mut self, atoms: &[Atom]) -> Result<(), Error> {
+        let xcon = self.display.xcon();
+        let mut cookies = Vec::new();
+        for atom in atoms {
+            if *atom != u32::from(AtomEnum::NONE) && !self.names.contains_key(atom) &&
+                !cookies.iter().any(|(fetching, _)| fetching == atom) {
+                cookies.push((*atom, xcon.get_atom_name(*atom)?));
+            }
+        }
+        for (atom, cookie) in cookies {
+            let name = cookie.reply()?.name;
+            self.names.insert(atom, String::from_utf8_lossy(&name).into_owned());
+        }
+        Ok(())
+    }
+
+    fn get(&self, atom: Atom) -> String {
+        self.names.get(&atom).cloned().unwrap_or_else(|| String::from("None"))
+    }
+}
+
+/// Reads every property of an output.
+pub fn list(display: &Display, output: u32) -> Result<Vec<OutputProperty>, Error> {
+    let xcon = display.xcon();
+    let atoms = xcon.randr_list_output_properties(output)?.reply()?.atoms;
+
+    // ask for everything first so it only takes a few round trips
+    let cookies = atoms.iter()
+        .map(|atom| {
+            Ok((*atom,
+                xcon.randr_query_output_property(output, *atom)?,
+                xcon.randr_get_output_property(output, *atom, AtomEnum::ANY, 0, MAX_LENGTH,
+                                               false, false)?))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    let mut replies = Vec::with_capacity(cookies.len());
+    for (atom, info, value) in cookies {
+        replies.push((atom, info.reply()?, value.reply()?));
+    }
+
+    let mut names = AtomNames::new(display);
+    let mut wanted = atoms.clone();
+    for (_, info, value) in &replies {
+        wanted.push(value.type_);
+        if value.type_ == u32::from(AtomEnum::ATOM) {
+            wanted.extend(decode_atoms(&value.data));
+            wanted.extend(info.valid_values.iter().map(|value| *value as Atom));
+        }
+    }
+    names.fetch(&wanted)?;
+
+    let properties = replies.into_iter()
+        .map(|(atom, info, value)| {
+            let is_atom = value.type_ == u32::from(AtomEnum::ATOM);
+            let decoded = if value.type_ == u32::from(AtomEnum::INTEGER) {
+                PropertyValue::Integers(decode_integers(value.format, &value.data, true))
+            }
+            else if value.type_ == u32::from(AtomEnum::CARDINAL) {
+                PropertyValue::Integers(decode_integers(value.format, &value.data, false))
+            }
+            else if is_atom {
+                PropertyValue::Atoms(decode_atoms(&value.data).into_iter()
+                    .map(|atom| names.get(atom))
+                    .collect())
+            }
+            else if value.type_ == u32::from(AtomEnum::STRING) {
+                PropertyValue::Text(String::from_utf8_lossy(&value.data).into_owned())
+            }
+            else {
+                PropertyValue::Other {
+                    type_name: names.get(value.type_),
+                    format: value.format,
+                    data: value.data
+                }
+            };
+            let valid_values = if info.range && info.valid_values.len() == 2 {
+                ValidValues::Range {
+                    min: info.valid_values[0],
+                    max: info.valid_values[1]
+                }
+            }
+            else if info.valid_values.is_empty() {
+                ValidValues::Any
+            }
+            else if is_atom {
+                ValidValues::Names(info.valid_values.iter().map(|value| names.get(*value as Atom))
+                    .collect())
+            }
+            else {
+                ValidValues::Integers(info.valid_values)
+            };
+
+            OutputProperty {
+                name: names.get(atom),
+                type_name: names.get(value.type_),
+                value: decoded,
+                valid_values,
+                immutable: info.immutable,
+                pending: info.pending
+            }
+        })
+        .collect();
+    Ok(properties)
+}
+
+/// Replaces the value of an output property with 32 bit items of the given type.
+pub fn change(display: &Display, output: u32, property: Atom, type_: impl Into<Atom>,
+              items: &[u32]) -> Result<(), Error> {
+    let data: Vec<u8> = items.iter().flat_map(|item| item.to_ne_bytes()).collect();
+    display.xcon()
+        .randr_change_output_property(output, property, type_.into(), 32, PropMode::REPLACE,
+                                      items.len() as u32, &data)?
+        .check()?;
+    Ok(())
+}
+
+/// Returns the atom of a property that is present on an output, along with the values it
+/// accepts and its type. Takes two round trips, the requests of each are sent at once.
+fn find(display: &Display, output: u32, name: &str)
+    -> Result<(Atom, QueryOutputPropertyReply, Atom), Error> {
+    let missing = || Error::MissingProperty {
+        output,
+        property: name.to_string()
+    };
+    let xcon = display.xcon();
+    let atom = xcon.intern_atom(true, name.as_bytes())?;
+    let present = xcon.randr_list_output_properties(output)?;
+    let atom = atom.reply()?.atom;
+    if atom == u32::from(AtomEnum::NONE) || !present.reply()?.atoms.contains(&atom) {
+        return Err(missing());
+    }
+
+    let info = xcon.randr_query_output_property(output, atom)?;
+    // only the type is of interest, not the value
+    let type_ = xcon.randr_get_output_property(output, atom, AtomEnum::ANY, 0, 0, false, false)?;
+    let info = info.reply()?;
+    let type_ = type_.reply()?.type_;
+    if info.immutable {
+        return Err(Error::Unsupported("changing immutable output properties"));
+    }
+    Ok((atom, info, type_))
+}
+
+/// Returns the item a number is stored as in a property of the given type, None if the type
+/// does not hold numbers or can't hold this one. `CARDINAL` numbers can't be negative.
+fn integer_item(type_: Atom, value: i32) -> Option<u32> {
+    if type_ == u32::from(AtomEnum::INTEGER) {
+        Some(value as u32)
+    }
+    else if type_ == u32::from(AtomEnum::CARDINAL) {
+        u32::try_from(value).ok()
+    }
+    else {
+        None
+    }
+}
+
+/// Sets an `INTEGER` or `CARDINAL` property, keeping its type and checking the value against the
+/// ones it accepts.
+pub fn set_integer(display: &Display, output: u32, name: &str, value: i32) -> Result<(), Error> {
+    let (atom, info, type_) = find(display, output, name)?;
+    let valid = if info.range && info.valid_values.len() == 2 {
+        (info.valid_values[0]..=info.valid_values[1]).contains(&value)
+    }
+    else {
+        info.valid_values.is_empty() || info.valid_values.contains(&value)
+    };
+    match integer_item(type_, value) {
+        Some(item) if valid => change(display, output, atom, type_, &[item]),
+        _ => Err(Error::InvalidPropertyValue {
+            property: name.to_string(),
+            value: value.to_string()
+        })
+    }
+}
+
+/// Sets an `ATOM` property to one of the names it accepts.
+pub fn set_name(display: &Display, output: u32, name: &str, value: &str) -> Result<(), Error> {
+    // sent ahead, so it is answered along with the requests of find
+    let value_atom = display.xcon().intern_atom(true, value.as_bytes())?;
+    let (atom, info, _) = find(display, output, name)?;
+    let invalid = || Error::InvalidPropertyValue {
+        property: name.to_string(),
+        value: value.to_string()
+    };
+    // a name no atom exists for can't be one of the valid values
+    let value_atom = value_atom.reply()?.atom;
+    if value_atom == u32::from(AtomEnum::NONE) ||
+        !info.valid_values.iter().any(|valid| *valid as Atom == value_atom) {
+        return Err(invalid());
+    }
+    change(display, output, atom, AtomEnum::ATOM, &[value_atom])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_integers() {
+        let data: Vec<u8> = [-1_i32, 12].iter().flat_map(|item| item.to_ne_bytes()).collect();
+        assert_eq!(decode_integers(32, &data, true), vec![-1, 12]);
+        assert_eq!(decode_integers(32, &data, false), vec![u32::MAX as i64, 12]);
+        assert_eq!(decode_integers(8, &[0xff, 1], true), vec![-1, 1]);
+        let data: Vec<u8> = [0xfffe_u16, 3].iter().flat_map(|item| item.to_ne_bytes()).collect();
+        assert_eq!(decode_integers(16, &data, true), vec![-2, 3]);
+
+        let data: Vec<u8> = [7_u32, 9].iter().flat_map(|item| item.to_ne_bytes()).collect();
+        assert_eq!(decode_atoms(&data), vec![7, 9]);
+    }
+
+    #[test]
+    fn stores_integers_by_type() {
+        assert_eq!(integer_item(AtomEnum::INTEGER.into(), -1), Some(u32::MAX));
+        assert_eq!(integer_item(AtomEnum::CARDINAL.into(), 10), Some(10));
+        assert_eq!(integer_item(AtomEnum::CARDINAL.into(), -1), None);
+        assert_eq!(integer_item(AtomEnum::ATOM.into(), 10), None);
+    }
+}
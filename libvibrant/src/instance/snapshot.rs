@@ -0,0 +1,101 @@
+use crate::instance::controller::{ColorMatrix, ControllerBackend};
+use crate::instance::gamma::GammaRamp;
+use crate::instance::identity::Identity;
+
+/// Everything libvibrant can change about the displays of an instance, see
+/// [`Instance::snapshot`](crate::Instance::snapshot). With the `serde` feature it can be
+/// serialized, e.g. to put the displays back from another process.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DisplayState {
+    pub controllers: Vec<ControllerState>
+}
+
+/// The state of a single controller.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ControllerState {
+    pub name: String,
+    /// Which monitor was attached, None if it has no EDID.
+    pub identity: Option<Identity>,
+    pub backend: ControllerBackend,
+    pub saturation: f64,
+    /// None if the backend does not support brightness, or the state predates it.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub brightness: Option<f64>,
+    /// None if the backend does not support color transforms.
+    pub matrix: Option<ColorMatrix>,
+    /// None if the display was turned off. For tiled monitors the ramps of the first tile, which
+    /// are restored to every tile.
+    pub gamma: Option<GammaRamp>
+}
+
+/// Decides which of the current displays each saved state belongs to, given the name and
+/// identity of every current display. Displays with an identity are matched by it, the name only
+/// breaks ties between identical monitors. Displays without one are matched by name. Every
+/// display is matched at most once.
+pub(crate) fn pair(saved: &[ControllerState],
+                   current: &[(String, Option<Identity>)]) -> Vec<Option<usize>> {
+    let mut taken = vec![false; current.len()];
+    let mut pairs = Vec::with_capacity(saved.len());
+
+    for state in saved {
+        let candidates: Vec<usize> = (0..current.len())
+            .filter(|idx| !taken[*idx] && current[*idx].1 == state.identity)
+            .filter(|idx| state.identity.is_some() || current[*idx].0 == state.name)
+            .collect();
+        let chosen = candidates.iter()
+            .find(|idx| current[**idx].0 == state.name)
+            .or_else(|| candidates.first())
+            .copied();
+
+        if let Some(idx) = chosen {
+            taken[idx] = true;
+        }
+        pairs.push(chosen);
+    }
+
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn identity(serial: u32) -> Option<Identity> {
+        Some(Identity {
+            manufacturer: "DEL".to_string(),
+            product: 0xa0f2,
+            serial,
+            name: None,
+            serial_number: None
+        })
+    }
+
+    fn state(name: &str, identity: Option<Identity>) -> ControllerState {
+        ControllerState {
+            name: name.to_string(),
+            identity,
+            backend: ControllerBackend::CTM,
+            saturation: 1.0,
+            brightness: None,
+            matrix: None,
+            gamma: None
+        }
+    }
+
+    #[test]
+    fn pairs_by_identity() {
+        let saved = [state("DP-1", identity(1)), state("DP-2", identity(2)),
+                     state("eDP-1", None), state("HDMI-1", None), state("DP-3", identity(3))];
+        // the monitors swapped ports
+        let current = [("DP-1".to_string(), identity(2)), ("DP-2".to_string(), identity(1)),
+                       ("eDP-1".to_string(), None), ("DP-3".to_string(), None)];
+        assert_eq!(pair(&saved, &current), vec![Some(1), Some(0), Some(2), None, None]);
+
+        // identical monitors without serials stay on their port
+        let saved = [state("DP-2", identity(0)), state("DP-1", identity(0))];
+        let current = [("DP-1".to_string(), identity(0)), ("DP-2".to_string(), identity(0))];
+        assert_eq!(pair(&saved, &current), vec![Some(1), Some(0)]);
+    }
+}
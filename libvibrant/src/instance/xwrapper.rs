@@ -1,5 +1,6 @@
 mod display;
 mod rroutput;
+pub mod nvcontrol;
 
 pub use display::Display;
 pub use rroutput::RROutput;
@@ -1,60 +1,111 @@
-use x11::xlib;
-use libXNVCtrl_sys as nvctrl;
+use x11rb::connection::RequestConnection;
+use x11rb::protocol::randr::ConnectionExt as _;
+use x11rb::protocol::xproto::{Atom, AtomEnum, ConnectionExt as _};
+use x11rb::rust_connection::RustConnection;
 use crate::instance::error::Error;
-use std::ffi::{CStr};
-use std::ptr::{null, null_mut};
+use crate::instance::xwrapper::nvcontrol;
+use std::ffi::CStr;
 
 pub struct Display {
-    xcon: *mut xlib::Display,
-    has_nvidia: bool
+    xcon: RustConnection,
+    /// The name the connection was opened with, None for $DISPLAY.
+    name: Option<String>,
+    randr_version: (u32, u32),
+    ctm_atom: Atom,
+    brightness_atom: Atom,
+    edid_atom: Atom,
+    backlight_atoms: Vec<Atom>,
+    nvcontrol_opcode: Option<u8>,
+    nvcontrol_first_event: u8
 }
 
 impl Display {
     pub fn from_display_name(name: Option<&CStr>) -> Result<Display, Error> {
-        // pointer to the name we give to the XOpenDisplay function
-        let name_ptr;
-        // the name that is returned in error messages
-        let name = if let Some(name) = name {
-            name_ptr = name.as_ptr();
-            String::from(name.to_string_lossy())
-        }
-        else{
-            name_ptr = null();
-            String::from("Null")
-        };
-
-        let xcon;
-        let has_nvidia;
+        // the name we hand to x11rb, None means use $DISPLAY
+        let name = name.map(|name| String::from(name.to_string_lossy()));
 
-        unsafe {
-            xcon = xlib::XOpenDisplay(name_ptr);
-            if xcon.is_null() {
-                return Err(Error::OpenDisplay(String::from(name)))
+        let (xcon, _) = match x11rb::connect(name.as_deref()) {
+            Ok(con) => con,
+            Err(_) => {
+                // the name that is returned in error messages
+                return Err(Error::OpenDisplay(name.unwrap_or_else(|| String::from("Null"))))
             }
-
-            has_nvidia = nvctrl::XNVCTRLQueryExtension(xcon, null_mut(), null_mut()) != 0;
         };
 
+        // the server only sends the events of the RandR version we claim to understand
+        let randr_version = xcon.randr_query_version(1, 5)?;
+        let ctm_atom = xcon.intern_atom(true, b"CTM")?;
+        // ours, so it is created if no client did yet
+        let brightness_atom = xcon.intern_atom(false, b"_VIBRANT_BRIGHTNESS")?;
+        let edid_atom = xcon.intern_atom(true, b"EDID")?;
+        let backlight_cookies = [&b"Backlight"[..], b"BACKLIGHT"].iter()
+            .map(|name| xcon.intern_atom(true, name))
+            .collect::<Result<Vec<_>, _>>()?;
+        let nvcontrol = xcon.extension_information(nvcontrol::EXTENSION_NAME)?;
+        let randr_version = randr_version.reply()?;
+        let mut backlight_atoms = Vec::with_capacity(backlight_cookies.len());
+        for cookie in backlight_cookies {
+            let atom = cookie.reply()?.atom;
+            if atom != u32::from(AtomEnum::NONE) {
+                backlight_atoms.push(atom);
+            }
+        }
 
         Ok(Display{
+            randr_version: (randr_version.major_version, randr_version.minor_version),
+            ctm_atom: ctm_atom.reply()?.atom,
+            brightness_atom: brightness_atom.reply()?.atom,
+            edid_atom: edid_atom.reply()?.atom,
+            backlight_atoms,
+            nvcontrol_opcode: nvcontrol.map(|ext| ext.major_opcode),
+            nvcontrol_first_event: nvcontrol.map(|ext| ext.first_event).unwrap_or(0),
             xcon,
-            has_nvidia
+            name
         })
     }
 
-    pub fn has_nvidia(&self) -> bool {
-        self.has_nvidia
+    /// Returns the major opcode the server assigned to NV-CONTROL, if the extension is present.
+    pub fn nvcontrol_opcode(&self) -> Option<u8> {
+        self.nvcontrol_opcode
     }
 
-    pub fn xcon(&self) -> *mut xlib::Display {
-        self.xcon
+    /// Returns true if the server speaks at least the given version of RandR.
+    pub fn has_randr(&self, major: u32, minor: u32) -> bool {
+        self.randr_version >= (major, minor)
     }
-}
 
-impl Drop for Display {
-    fn drop(&mut self) {
-        unsafe {
-            xlib::XCloseDisplay(self.xcon)
-        };
+    /// Returns the event code of NV-CONTROL's first event.
+    pub fn nvcontrol_first_event(&self) -> u8 {
+        self.nvcontrol_first_event
+    }
+
+    /// Returns the atom of the CTM output property, or `AtomEnum::NONE` if no output has it.
+    pub fn ctm_atom(&self) -> Atom {
+        self.ctm_atom
+    }
+
+    /// Returns the atom of the output property the CTM backend keeps the brightness in.
+    pub fn brightness_atom(&self) -> Atom {
+        self.brightness_atom
+    }
+
+    /// Returns the atom of the EDID output property, or `AtomEnum::NONE` if no output has it.
+    pub fn edid_atom(&self) -> Atom {
+        self.edid_atom
+    }
+
+    /// Returns the atoms of the backlight output properties that exist on the server, there are
+    /// two spellings of the name.
+    pub fn backlight_atoms(&self) -> &[Atom] {
+        &self.backlight_atoms
+    }
+
+    /// Returns the name the connection was opened with, None if it was opened through $DISPLAY.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn xcon(&self) -> &RustConnection {
+        &self.xcon
     }
 }
@@ -0,0 +1,232 @@
+//! A native encoding of the parts of NVIDIA's NV-CONTROL protocol that we use. This is what
+//! libXNVCtrl sends on the wire, see nv_control.h in nvidia-settings for the original layouts.
+
+use std::convert::TryFrom;
+use std::io::IoSlice;
+use x11rb::connection::RequestConnection;
+use x11rb::cookie::{Cookie, VoidCookie};
+use x11rb::errors::{ConnectionError, ParseError};
+use x11rb::x11_utils::TryParse;
+
+pub const EXTENSION_NAME: &str = "NV-CONTROL";
+
+const QUERY_ATTRIBUTE_REQUEST: u8 = 2;
+const SET_ATTRIBUTE_REQUEST: u8 = 3;
+const QUERY_BINARY_DATA_REQUEST: u8 = 20;
+const SELECT_TARGET_NOTIFY_REQUEST: u8 = 23;
+
+/// Offset of the event sent for changed attributes of targets other than X screens.
+pub const TARGET_ATTRIBUTE_CHANGED_EVENT: u8 = 1;
+
+pub const TARGET_TYPE_X_SCREEN: u16 = 0;
+pub const TARGET_TYPE_DISPLAY: u16 = 8;
+
+pub const DIGITAL_VIBRANCE: u32 = 4;
+pub const DISPLAY_RANDR_OUTPUT_ID: u32 = 391;
+
+pub const BINARY_DATA_DISPLAYS_ENABLED_ON_XSCREEN: u32 = 17;
+
+/// Every reply starts with a 32 byte block, any extra data follows it.
+struct RawReply<'a> {
+    /// The 24 bytes of the fixed block that follow the generic reply header.
+    fixed: &'a [u8],
+    /// The extra data.
+    data: &'a [u8],
+    /// Whatever is left after the reply.
+    remaining: &'a [u8]
+}
+
+fn split_reply(value: &[u8]) -> Result<RawReply<'_>, ParseError> {
+    if value.len() < 32 {
+        return Err(ParseError::InsufficientData);
+    }
+
+    let (length, _) = u32::try_parse(&value[4..8])?;
+    let total = 32 + length as usize * 4;
+    if value.len() < total {
+        return Err(ParseError::InsufficientData);
+    }
+
+    Ok(RawReply{
+        fixed: &value[8..32],
+        data: &value[32..total],
+        remaining: &value[total..]
+    })
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct QueryAttributeReply {
+    /// Non zero if the attribute could be queried for the given target.
+    pub flags: u32,
+    pub value: i32
+}
+
+impl TryParse for QueryAttributeReply {
+    fn try_parse(value: &[u8]) -> Result<(Self, &[u8]), ParseError> {
+        let reply = split_reply(value)?;
+        let (flags, fixed) = u32::try_parse(reply.fixed)?;
+        let (value, _) = i32::try_parse(fixed)?;
+
+        Ok((QueryAttributeReply{flags, value}, reply.remaining))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct QueryBinaryDataReply {
+    /// Non zero if the data could be queried for the given target.
+    pub flags: u32,
+    pub data: Vec<u8>
+}
+
+impl TryParse for QueryBinaryDataReply {
+    fn try_parse(value: &[u8]) -> Result<(Self, &[u8]), ParseError> {
+        let reply = split_reply(value)?;
+        let (flags, fixed) = u32::try_parse(reply.fixed)?;
+        // the number of meaningful bytes, the rest of the data is padding
+        let (n, _) = u32::try_parse(fixed)?;
+        let data = reply.data.get(..n as usize).ok_or(ParseError::InsufficientData)?;
+
+        Ok((QueryBinaryDataReply{flags, data: data.to_vec()}, reply.remaining))
+    }
+}
+
+/// Sent for a target that has been selected with `select_target_notify`.
+#[derive(Debug, Clone, Copy)]
+pub struct TargetAttributeChangedEvent {
+    pub target_id: u16,
+    pub target_type: u16,
+    pub attribute: u32
+}
+
+impl TryParse for TargetAttributeChangedEvent {
+    fn try_parse(value: &[u8]) -> Result<(Self, &[u8]), ParseError> {
+        if value.len() < 32 {
+            return Err(ParseError::InsufficientData);
+        }
+
+        // skip type, detail, sequence number and time
+        let (target_id, remaining) = u16::try_parse(&value[8..])?;
+        let (target_type, remaining) = u16::try_parse(remaining)?;
+        let (_display_mask, remaining) = u32::try_parse(remaining)?;
+        let (attribute, _) = u32::try_parse(remaining)?;
+
+        Ok((TargetAttributeChangedEvent{target_id, target_type, attribute}, &value[32..]))
+    }
+}
+
+/// Parses the data of BINARY_DATA_DISPLAYS_ENABLED_ON_XSCREEN. It is a list of ints, the first of
+/// which is how many display ids follow it.
+pub fn parse_display_ids(data: &[u8]) -> Result<Vec<u16>, ParseError> {
+    let (len, mut remaining) = match data.len() {
+        0 => return Ok(Vec::new()),
+        _ => i32::try_parse(data)?
+    };
+    let len = usize::try_from(len).map_err(|_| ParseError::InvalidValue)?;
+
+    let mut ids = Vec::with_capacity(len.min(remaining.len() / 4));
+    for _ in 0..len {
+        let (id, rest) = i32::try_parse(remaining)?;
+        ids.push(u16::try_from(id).map_err(|_| ParseError::InvalidValue)?);
+        remaining = rest;
+    }
+
+    Ok(ids)
+}
+
+/// Builds the request shared by QueryAttribute and QueryBinaryData, they only differ in the minor
+/// opcode.
+fn target_request(major_opcode: u8, minor_opcode: u8, target_type: u16, target_id: u16,
+                  display_mask: u32, attribute: u32) -> [u8; 16] {
+    let mut request = [0; 16];
+    request[0] = major_opcode;
+    request[1] = minor_opcode;
+    request[2..4].copy_from_slice(&(16u16 / 4).to_ne_bytes());
+    request[4..6].copy_from_slice(&target_id.to_ne_bytes());
+    request[6..8].copy_from_slice(&target_type.to_ne_bytes());
+    request[8..12].copy_from_slice(&display_mask.to_ne_bytes());
+    request[12..16].copy_from_slice(&attribute.to_ne_bytes());
+    request
+}
+
+pub fn query_target_attribute<Conn>(conn: &Conn, major_opcode: u8, target_type: u16,
+                                    target_id: u16, display_mask: u32, attribute: u32)
+    -> Result<Cookie<'_, Conn, QueryAttributeReply>, ConnectionError>
+    where Conn: RequestConnection + ?Sized {
+    let request = target_request(major_opcode, QUERY_ATTRIBUTE_REQUEST, target_type, target_id,
+                                 display_mask, attribute);
+    conn.send_request_with_reply(&[IoSlice::new(&request)], Vec::new())
+}
+
+pub fn set_target_attribute<Conn>(conn: &Conn, major_opcode: u8, target_type: u16,
+                                  target_id: u16, display_mask: u32, attribute: u32, value: i32)
+    -> Result<VoidCookie<'_, Conn>, ConnectionError>
+    where Conn: RequestConnection + ?Sized {
+    let mut request = [0; 20];
+    request[0] = major_opcode;
+    request[1] = SET_ATTRIBUTE_REQUEST;
+    request[2..4].copy_from_slice(&(20u16 / 4).to_ne_bytes());
+    request[4..6].copy_from_slice(&target_id.to_ne_bytes());
+    request[6..8].copy_from_slice(&target_type.to_ne_bytes());
+    request[8..12].copy_from_slice(&display_mask.to_ne_bytes());
+    request[12..16].copy_from_slice(&attribute.to_ne_bytes());
+    request[16..20].copy_from_slice(&value.to_ne_bytes());
+    conn.send_request_without_reply(&[IoSlice::new(&request)], Vec::new())
+}
+
+pub fn query_binary_data<Conn>(conn: &Conn, major_opcode: u8, target_type: u16, target_id: u16,
+                               display_mask: u32, attribute: u32)
+    -> Result<Cookie<'_, Conn, QueryBinaryDataReply>, ConnectionError>
+    where Conn: RequestConnection + ?Sized {
+    let request = target_request(major_opcode, QUERY_BINARY_DATA_REQUEST, target_type, target_id,
+                                 display_mask, attribute);
+    conn.send_request_with_reply(&[IoSlice::new(&request)], Vec::new())
+}
+
+pub fn select_target_notify<Conn>(conn: &Conn, major_opcode: u8, target_type: u16,
+                                  target_id: u16, notify_type: u8, on: bool)
+    -> Result<VoidCookie<'_, Conn>, ConnectionError>
+    where Conn: RequestConnection + ?Sized {
+    let mut request = [0; 12];
+    request[0] = major_opcode;
+    request[1] = SELECT_TARGET_NOTIFY_REQUEST;
+    request[2..4].copy_from_slice(&(12u16 / 4).to_ne_bytes());
+    request[4..6].copy_from_slice(&target_id.to_ne_bytes());
+    request[6..8].copy_from_slice(&target_type.to_ne_bytes());
+    request[8..10].copy_from_slice(&u16::from(notify_type).to_ne_bytes());
+    request[10..12].copy_from_slice(&u16::from(on).to_ne_bytes());
+    conn.send_request_without_reply(&[IoSlice::new(&request)], Vec::new())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_binary_data_reply() {
+        // two ids, 12 bytes of data padded up to 16
+        let mut reply = vec![1, 0];
+        reply.extend_from_slice(&7u16.to_ne_bytes());
+        reply.extend_from_slice(&4u32.to_ne_bytes());
+        reply.extend_from_slice(&1u32.to_ne_bytes());
+        reply.extend_from_slice(&12u32.to_ne_bytes());
+        reply.resize(32, 0);
+        for value in &[2i32, 0x100, 0x200] {
+            reply.extend_from_slice(&value.to_ne_bytes());
+        }
+        reply.resize(48, 0);
+
+        let (parsed, remaining) = QueryBinaryDataReply::try_parse(&reply).unwrap();
+        assert_eq!(parsed.flags, 1);
+        assert_eq!(parsed.data.len(), 12);
+        assert!(remaining.is_empty());
+        assert!(QueryBinaryDataReply::try_parse(&reply[..40]).is_err());
+        assert_eq!(parse_display_ids(&parsed.data).unwrap(), vec![0x100, 0x200]);
+    }
+
+    #[test]
+    fn rejects_truncated_display_ids() {
+        let data: Vec<u8> = [3i32, 0x100].iter().flat_map(|value| value.to_ne_bytes()).collect();
+        assert!(parse_display_ids(&data).is_err());
+        assert!(parse_display_ids(&[]).unwrap().is_empty());
+    }
+}
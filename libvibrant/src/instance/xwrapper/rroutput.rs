@@ -1,63 +1,52 @@
-use x11::{xrandr, xlib};
+use x11rb::connection::Connection;
+use x11rb::protocol::randr::{self, ConnectionExt};
 use super::display::Display;
-use std::slice::from_raw_parts;
-use std::ffi::CStr;
+use crate::instance::error::Error;
 
 pub struct RROutput {
-    output: xrandr::RROutput,
-    info: *mut xrandr::XRROutputInfo
+    output: randr::Output,
+    name: String
 }
 
 impl RROutput {
-    pub fn from_display(display: &Display) -> Vec<RROutput> {
-        let root;
-        let screen_resources    ;
-        let mut outputs;
-        let outputs_slice;
-        unsafe {
-            root = xlib::XDefaultRootWindow(display.xcon());
-            screen_resources = xrandr::XRRGetScreenResources(display.xcon(), root);
-            outputs = Vec::with_capacity((*screen_resources).noutput as usize);
-            outputs_slice = from_raw_parts((*screen_resources).outputs,
-                                           (*screen_resources).noutput as usize);
-        };
-
-
-        for output in outputs_slice {
-            let output = *output;
-            let info;
-            unsafe {
-                info = xrandr::XRRGetOutputInfo(display.xcon(), screen_resources, output);
-                if (*info).connection == xrandr::RR_Connected as u16 {
-                    outputs.push(RROutput{
-                        output,
-                        info
-                    })
-                }
+    /// Returns the connected outputs of every screen of the display.
+    pub fn from_display(display: &Display) -> Result<Vec<RROutput>, Error> {
+        let xcon = display.xcon();
+
+        // send every request before waiting on any of the replies
+        let resource_cookies = xcon.setup().roots.iter()
+            .map(|screen| xcon.randr_get_screen_resources(screen.root))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut cookies = Vec::new();
+        for cookie in resource_cookies {
+            let screen_resources = cookie.reply()?;
+            for output in &screen_resources.outputs {
+                let cookie = xcon.randr_get_output_info(*output,
+                                                        screen_resources.config_timestamp)?;
+                cookies.push((*output, cookie));
             }
         }
 
-        outputs
+        let mut outputs = Vec::with_capacity(cookies.len());
+        for (output, cookie) in cookies {
+            let info = cookie.reply()?;
+            if info.connection == randr::Connection::CONNECTED {
+                outputs.push(RROutput{
+                    output,
+                    name: String::from_utf8_lossy(&info.name).into_owned()
+                })
+            }
+        }
+
+        Ok(outputs)
     }
 
-    pub fn id(&self) -> xrandr::RROutput {
+    pub fn id(&self) -> randr::Output {
         self.output
     }
 
     pub fn name(&self) -> String {
-        unsafe {
-            let c_str =
-                CStr::from_bytes_with_nul_unchecked(from_raw_parts((*self.info).name as *const _,
-                                                                   (*self.info).nameLen as usize + 1));
-            c_str.to_string_lossy().to_string()
-        }
-    }
-}
-
-impl Drop for RROutput {
-    fn drop(&mut self) {
-        unsafe {
-            xrandr::XRRFreeOutputInfo(self.info);
-        }
+        self.name.clone()
     }
 }
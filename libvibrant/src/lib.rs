@@ -1,9 +1,24 @@
+pub mod animation;
+pub mod color;
+pub mod guard;
 pub mod instance;
+pub mod profiles;
+pub mod schedule;
+#[cfg(feature = "async")]
+pub mod asynchronous;
 
 pub use instance::Instance;
-pub use instance::Controller;
+pub use instance::ControllerRef;
 pub use instance::Error;
-pub use instance::ControllerBackend;
+pub use instance::{ColorMatrix, ControllerBackend};
+pub use instance::Event;
+pub use instance::Identity;
+pub use instance::{BacklightRange, ControllerState, DisplayState, GammaRamp};
+pub use instance::{OutputInfo, ModeInfo, Rotation};
+pub use instance::{BroadcastRgb, OutputProperty, PropertyValue, ValidValues};
+pub use instance::CallbackId;
+pub use guard::{SaturationGuard, TransformGuard};
+pub use x11rb;
 
 #[cfg(test)]
 mod tests {
@@ -11,14 +26,44 @@ mod tests {
 
     #[test]
     fn it_works() {
+        // a failed assertion must not leave the desktop changed
+        crate::guard::install_panic_hook();
         let instance = Instance::new().unwrap();
         let controllers = instance.controllers();
-        for controller in controllers {
-            let old_saturation = controller.get_saturation(&instance);
+        for controller in &controllers {
+            let guard = controller.set_saturation_scoped(1.0).unwrap();
             println!("{} ({}): {}", controller.get_backend(),
-                     controller.get_name(), old_saturation);
-            controller.set_saturation(&instance, 1.0);
-            controller.set_saturation(&instance, old_saturation);
+                     controller.get_name(), guard.previous());
+            guard.restore().unwrap();
         }
     }
+
+    #[test]
+    fn restores_brightness() {
+        let instance = Instance::new().unwrap();
+        let original = instance.snapshot().unwrap();
+        let dimmed: Vec<_> = instance.controllers().into_iter()
+            .filter(|controller| controller.set_brightness(0.4).is_ok())
+            .collect();
+        let state = instance.snapshot().unwrap();
+        for controller in &dimmed {
+            controller.set_brightness(0.2).unwrap();
+        }
+
+        instance.restore(&state).unwrap();
+        let restored = dimmed.iter()
+            .map(|controller| controller.get_brightness().unwrap())
+            .collect::<Vec<_>>();
+        instance.restore(&original).unwrap();
+        for level in restored {
+            assert!((level - 0.4).abs() < 1e-9, "{} != 0.4", level);
+        }
+    }
+
+    #[test]
+    fn instance_is_thread_safe() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Instance>();
+        assert_send_sync::<crate::ControllerRef<'_>>();
+    }
 }
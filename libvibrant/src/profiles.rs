@@ -0,0 +1,582 @@
+//! Saturation that follows what the user is doing.
+//!
+//! [`Profiles`] applies a saturation whenever a window of an application gets focused, it follows
+//! the EWMH `_NET_ACTIVE_WINDOW` property of the root windows. [`Workspaces`] applies a saturation
+//! or color transform to every virtual desktop, following `_NET_CURRENT_DESKTOP`. Both need a
+//! window manager that sets these properties, which almost all of them do.
+//!
+//! ```no_run
+//! use libvibrant::Instance;
+//! use libvibrant::profiles::{Profiles, Rule};
+//!
+//! let instance = Instance::new().unwrap();
+//! let rules = vec![Rule {
+//!     class: Some("csgo_linux64".to_string()),
+//!     ..Rule::new(2.5)
+//! }];
+//! let mut profiles = Profiles::new(&instance, rules).unwrap();
+//! loop {
+//!     let event = instance.wait_event().unwrap();
+//!     profiles.handle_event(&event).unwrap();
+//! }
+//! ```
+
+mod workspace;
+
+pub use workspace::{Desktop, DesktopRule, Workspaces};
+use crate::{ColorMatrix, ControllerRef, Error, Event, Instance};
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::Entry;
+use std::fs;
+use std::path::{Path, PathBuf};
+use x11rb::connection::Connection;
+use x11rb::errors::ReplyError;
+use x11rb::protocol::xproto::{AtomEnum, ChangeWindowAttributesAux, ConnectionExt as _,
+                              EventMask};
+use x11rb::rust_connection::RustConnection;
+
+x11rb::atom_manager! {
+    Atoms: AtomsCookie {
+        _NET_ACTIVE_WINDOW,
+        _NET_WM_NAME,
+        _NET_WM_PID,
+        _NET_WM_STATE,
+        _NET_WM_STATE_FULLSCREEN,
+        _NET_CURRENT_DESKTOP,
+        _NET_DESKTOP_NAMES,
+        UTF8_STRING,
+    }
+}
+
+/// A change to the colors of a display.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Adjustment {
+    Saturation(f64),
+    /// Only supported by the CTM backend.
+    Transform(ColorMatrix)
+}
+
+impl Adjustment {
+    /// Applies the adjustment to the display of a controller.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the server rejected the new value, or [`Error::Unsupported`] if the
+    /// backend of the controller can not apply color transforms.
+    pub fn apply(&self, controller: &ControllerRef<'_>) -> Result<(), Error> {
+        match self {
+            Adjustment::Saturation(saturation) => controller.set_saturation(*saturation),
+            Adjustment::Transform(matrix) => controller.set_color_transform(matrix)
+        }
+    }
+
+    /// Returns how the display of a controller currently looks, as precisely as its backend
+    /// allows: the color transform if the backend has one, the saturation otherwise.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the server failed to answer.
+    pub fn current(controller: &ControllerRef<'_>) -> Result<Adjustment, Error> {
+        match controller.get_color_transform() {
+            Ok(matrix) => Ok(Adjustment::Transform(matrix)),
+            Err(Error::Unsupported(_)) => Ok(Adjustment::Saturation(controller.get_saturation()?)),
+            Err(error) => Err(error)
+        }
+    }
+}
+
+/// How displays looked before a profile changed them, by output id.
+#[derive(Default)]
+struct Defaults(HashMap<u32, Adjustment>);
+
+impl Defaults {
+    /// Applies an adjustment, remembering how the display looked if it has not been changed yet.
+    /// Nothing is remembered if the adjustment could not be applied.
+    fn change(&mut self, controller: &ControllerRef<'_>,
+              adjustment: &Adjustment) -> Result<(), Error> {
+        let previous = match self.0.entry(controller.get_output_id()) {
+            Entry::Vacant(_) => Some(Adjustment::current(controller)?),
+            Entry::Occupied(_) => None
+        };
+        adjustment.apply(controller)?;
+        if let Some(previous) = previous {
+            self.0.insert(controller.get_output_id(), previous);
+        }
+        Ok(())
+    }
+
+    /// Puts a display back to how it looked before it was first changed.
+    fn restore(&mut self, controller: &ControllerRef<'_>) -> Result<(), Error> {
+        // forget it, so changes made while no profile is applied are kept next time
+        match self.0.remove(&controller.get_output_id()) {
+            Some(adjustment) => adjustment.apply(controller),
+            None => Ok(())
+        }
+    }
+}
+
+/// Decides which windows get a different saturation. Every condition that is set has to match,
+/// a rule without conditions matches every window.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    /// Either part of `WM_CLASS`, the instance or the class name, has to be exactly this.
+    pub class: Option<String>,
+    /// The title of the window has to contain this.
+    pub title: Option<String>,
+    /// The executable of the process that owns the window. A bare file name matches the file
+    /// name of the executable, anything with a `/` has to match the whole path.
+    pub executable: Option<String>,
+    /// Only match while the window is fullscreen. The saturation is then only applied to the
+    /// display showing the window, instead of every display.
+    pub fullscreen: bool,
+    /// The saturation to apply while a matching window is focused.
+    pub saturation: f64
+}
+
+impl Rule {
+    /// Creates a rule without conditions.
+    pub fn new(saturation: f64) -> Rule {
+        Rule {
+            class: None,
+            title: None,
+            executable: None,
+            fullscreen: false,
+            saturation
+        }
+    }
+
+    /// Returns if the window satisfies every condition of this rule.
+    pub fn matches(&self, window: &WindowInfo) -> bool {
+        if self.fullscreen && !window.fullscreen {
+            return false;
+        }
+
+        if let Some(class) = &self.class {
+            if window.instance_name.as_ref() != Some(class) && window.class.as_ref() != Some(class) {
+                return false;
+            }
+        }
+
+        if let Some(title) = &self.title {
+            if !window.title.as_ref().is_some_and(|window_title| window_title.contains(title)) {
+                return false;
+            }
+        }
+
+        if let Some(executable) = &self.executable {
+            let path = match &window.executable {
+                Some(path) => path,
+                None => return false
+            };
+            let matches = if executable.contains('/') {
+                path == Path::new(executable)
+            }
+            else {
+                path.file_name().is_some_and(|name| name == executable.as_str())
+            };
+            if !matches {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+/// What we know about a window when matching it against rules.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WindowInfo {
+    pub id: u32,
+    /// The first part of `WM_CLASS`, usually the name the application was started as.
+    pub instance_name: Option<String>,
+    /// The second part of `WM_CLASS`, the general name of the application.
+    pub class: Option<String>,
+    /// `_NET_WM_NAME`, or `WM_NAME` for windows that don't set it.
+    pub title: Option<String>,
+    /// `_NET_WM_PID`, only meaningful if the application runs on this machine.
+    pub pid: Option<u32>,
+    /// The executable behind the pid, resolved through `/proc`.
+    pub executable: Option<PathBuf>,
+    /// If the window asked to be fullscreen through `_NET_WM_STATE`, or covers a display exactly.
+    pub fullscreen: bool
+}
+
+impl WindowInfo {
+    fn query(xcon: &RustConnection, atoms: &Atoms, window: u32) -> Result<WindowInfo, Error> {
+        let class = xcon.get_property(false, window, AtomEnum::WM_CLASS, AtomEnum::STRING,
+                                      0, 1024)?;
+        let net_name = xcon.get_property(false, window, atoms._NET_WM_NAME, atoms.UTF8_STRING,
+                                         0, 1024)?;
+        let name = xcon.get_property(false, window, AtomEnum::WM_NAME, AtomEnum::ANY, 0, 1024)?;
+        let pid = xcon.get_property(false, window, atoms._NET_WM_PID, AtomEnum::CARDINAL, 0, 1)?;
+        let state = xcon.get_property(false, window, atoms._NET_WM_STATE, AtomEnum::ATOM, 0, 64)?;
+
+        let class = class.reply()?.value;
+        // WM_CLASS is two null terminated strings back to back
+        let mut class = class.split(|c| *c == 0)
+            .map(|part| String::from_utf8_lossy(part).into_owned());
+        let instance_name = class.next().filter(|part| !part.is_empty());
+        let class = class.next().filter(|part| !part.is_empty());
+
+        let net_name = net_name.reply()?.value;
+        let name = name.reply()?.value;
+        let title = if !net_name.is_empty() {
+            Some(String::from_utf8_lossy(&net_name).into_owned())
+        }
+        else if !name.is_empty() {
+            Some(String::from_utf8_lossy(&name).into_owned())
+        }
+        else {
+            None
+        };
+
+        let pid = pid.reply()?.value32().and_then(|mut values| values.next());
+        let executable = pid.and_then(|pid| fs::read_link(format!("/proc/{}/exe", pid)).ok());
+
+        let fullscreen = state.reply()?.value32()
+            .is_some_and(|mut states| states.any(|state| state == atoms._NET_WM_STATE_FULLSCREEN));
+
+        Ok(WindowInfo {
+            id: window,
+            instance_name,
+            class,
+            title,
+            pid,
+            executable,
+            fullscreen
+        })
+    }
+}
+
+/// A rule that is in effect.
+#[derive(Debug, PartialEq)]
+struct Applied {
+    rule: usize,
+    /// The outputs it is applied to, None for every output.
+    outputs: Option<Vec<u32>>
+}
+
+/// Applies the saturation of the first rule matching the focused window, and restores the
+/// previous saturation once no matching window is focused anymore.
+///
+/// Events have to be fed to it through [`Profiles::handle_event`].
+pub struct Profiles<'a> {
+    instance: &'a Instance,
+    atoms: Atoms,
+    rules: Vec<Rule>,
+    defaults: Defaults,
+    /// The focused window, which we watch for changes of its title and state.
+    active: Option<u32>,
+    current: Option<Applied>,
+    /// Outputs that are left alone, see [`Profiles::hold`].
+    held: HashSet<u32>
+}
+
+impl<'a> Profiles<'a> {
+    /// Starts watching the focused window and applies the rule matching it right away. This
+    /// selects events on the instance.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the server failed to answer or rejected a new saturation.
+    pub fn new(instance: &'a Instance, rules: Vec<Rule>) -> Result<Profiles<'a>, Error> {
+        Profiles::holding(instance, rules, HashSet::new())
+    }
+
+    /// Like [`Profiles::new`], but the given outputs are left alone from the start, as if they
+    /// had been passed to [`Profiles::hold`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the server failed to answer or rejected a new saturation.
+    pub fn holding(instance: &'a Instance, rules: Vec<Rule>,
+                   held: HashSet<u32>) -> Result<Profiles<'a>, Error> {
+        let atoms = Atoms::new(instance.xcon())?.reply()?;
+        watch_roots(instance)?;
+
+        let mut profiles = Profiles {
+            instance,
+            atoms,
+            rules,
+            defaults: Defaults::default(),
+            active: None,
+            current: None,
+            held
+        };
+        profiles.update()?;
+        Ok(profiles)
+    }
+
+    /// Returns the rules, in the order they are tried.
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    /// Returns the rule that is currently applied, None if the focused window matches none.
+    pub fn active_rule(&self) -> Option<&Rule> {
+        self.current.as_ref().map(|applied| &self.rules[applied.rule])
+    }
+
+    /// Reacts to an event of the instance. Every event has to be passed here, the ones that do
+    /// not concern profiles are ignored.
+    ///
+    /// Newly connected displays are picked up by refreshing the instance.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the server failed to answer or rejected a new saturation.
+    pub fn handle_event(&mut self, event: &Event) -> Result<(), Error> {
+        match event {
+            Event::WindowPropertyChanged { window, property }
+            if *property == self.atoms._NET_ACTIVE_WINDOW && is_root(self.instance, *window) => {
+                self.update()
+            }
+            Event::WindowPropertyChanged { window, property } if Some(*window) == self.active => {
+                let watched = [self.atoms._NET_WM_STATE, self.atoms._NET_WM_NAME,
+                    AtomEnum::WM_NAME.into()];
+                if watched.contains(property) {
+                    self.update()
+                }
+                else {
+                    Ok(())
+                }
+            }
+            Event::WindowGeometryChanged(window) if Some(*window) == self.active => self.update(),
+            // a display covered by a fullscreen window may have changed its resolution
+            Event::ScreenChanged => self.update(),
+            Event::OutputConnected(_) => {
+                self.instance.refresh()?;
+                self.apply()
+            }
+            _ => Ok(())
+        }
+    }
+
+    /// Puts back the saturation every display had before a rule was applied.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the server rejected a saturation.
+    pub fn restore(&mut self) -> Result<(), Error> {
+        self.current = None;
+        self.apply()
+    }
+
+    /// Applies the current rule to the displays the instance knows now, for callers that
+    /// refreshed the instance themselves. [`Profiles::handle_event`] refreshes and does this on
+    /// its own for [`Event::OutputConnected`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the server rejected a saturation.
+    pub fn outputs_changed(&mut self) -> Result<(), Error> {
+        self.apply()
+    }
+
+    /// Leaves the display of an output alone until it is released, for when something else is
+    /// in charge of it for a while.
+    pub fn hold(&mut self, output: u32) {
+        self.held.insert(output);
+    }
+
+    /// Stops leaving the display of an output alone and applies what the rules want for it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the server rejected a saturation.
+    pub fn release(&mut self, output: u32) -> Result<(), Error> {
+        if self.held.remove(&output) {
+            self.apply()?;
+        }
+        Ok(())
+    }
+
+    /// Returns the focused window, if any screen has one.
+    fn active_window(&self) -> Result<Option<u32>, Error> {
+        let xcon = self.instance.xcon();
+        let cookies = xcon.setup().roots.iter()
+            .map(|screen| xcon.get_property(false, screen.root, self.atoms._NET_ACTIVE_WINDOW,
+                                            AtomEnum::WINDOW, 0, 1))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut active = None;
+        for cookie in cookies {
+            let window = cookie.reply()?.value32().and_then(|mut values| values.next());
+            if active.is_none() {
+                active = window.filter(|window| *window != x11rb::NONE);
+            }
+        }
+
+        Ok(active)
+    }
+
+    /// Follows changes of the given window instead of the previously focused one.
+    fn watch(&mut self, window: Option<u32>) -> Result<(), Error> {
+        let xcon = self.instance.xcon();
+        if let Some(previous) = self.active.take() {
+            let attributes = ChangeWindowAttributesAux::new().event_mask(EventMask::NO_EVENT);
+            ignore_bad_window(xcon.change_window_attributes(previous, &attributes)?.check()
+                .map_err(Error::from))?;
+        }
+        if let Some(window) = window {
+            let attributes = ChangeWindowAttributesAux::new()
+                .event_mask(EventMask::PROPERTY_CHANGE | EventMask::STRUCTURE_NOTIFY);
+            ignore_bad_window(xcon.change_window_attributes(window, &attributes)?.check()
+                .map_err(Error::from))?;
+        }
+
+        self.active = window;
+        Ok(())
+    }
+
+    /// Looks up the rule for the focused window and applies it if it changed.
+    fn update(&mut self) -> Result<(), Error> {
+        let window = self.active_window()?;
+        if window != self.active {
+            self.watch(window)?;
+        }
+
+        let applied = match window {
+            Some(window) => ignore_bad_window(self.rule_for(window))?.flatten(),
+            None => None
+        };
+        if applied == self.current {
+            return Ok(());
+        }
+
+        self.current = applied;
+        self.apply()
+    }
+
+    /// Returns the rule that applies to the window.
+    fn rule_for(&self, window: u32) -> Result<Option<Applied>, Error> {
+        let mut info = WindowInfo::query(self.instance.xcon(), &self.atoms, window)?;
+
+        // finding the displays showing the window takes a few round trips, skip it if no rule
+        // cares about them
+        let mut shown_on = Vec::new();
+        if self.rules.iter().any(|rule| rule.fullscreen) {
+            let (screen, x, y, width, height) = self.instance.window_geometry(window)?;
+            let mut largest = 0;
+            for controller in self.instance.controllers() {
+                let output = controller.output_info()?;
+                if output.screen != screen || output.crtc.is_none() {
+                    continue;
+                }
+
+                // games often just resize their window to the display
+                info.fullscreen |= (output.x, output.y, output.width, output.height) ==
+                    (x, y, width, height);
+
+                // mirrored displays show the same part of the window
+                let overlap = output.overlap(x, y, width, height);
+                if overlap > largest {
+                    largest = overlap;
+                    shown_on.clear();
+                }
+                if overlap > 0 && overlap == largest {
+                    shown_on.push(controller.get_output_id());
+                }
+            }
+        }
+
+        Ok(self.rules.iter().position(|rule| rule.matches(&info)).map(|rule| Applied {
+            rule,
+            outputs: if self.rules[rule].fullscreen { Some(shown_on) } else { None }
+        }))
+    }
+
+    /// Sets the displays the current rule applies to, and every other display back to its
+    /// default. Held displays are skipped.
+    fn apply(&mut self) -> Result<(), Error> {
+        for controller in self.instance.controllers() {
+            let output = controller.get_output_id();
+            if self.held.contains(&output) {
+                continue;
+            }
+            let saturation = self.current.as_ref()
+                .filter(|applied| {
+                    applied.outputs.as_ref().is_none_or(|outputs| outputs.contains(&output))
+                })
+                .map(|applied| self.rules[applied.rule].saturation);
+
+            match saturation {
+                Some(saturation) => {
+                    self.defaults.change(&controller, &Adjustment::Saturation(saturation))?
+                }
+                None => self.defaults.restore(&controller)?
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Selects property changes of every root window and events on the instance.
+fn watch_roots(instance: &Instance) -> Result<(), Error> {
+    let xcon = instance.xcon();
+    let attributes = ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE);
+    for screen in &xcon.setup().roots {
+        xcon.change_window_attributes(screen.root, &attributes)?.check()?;
+    }
+    instance.select_events()
+}
+
+/// Returns if the window is one of the root windows.
+fn is_root(instance: &Instance, window: u32) -> bool {
+    instance.xcon().setup().roots.iter().any(|screen| screen.root == window)
+}
+
+/// Turns errors about windows that were destroyed before we got to them into None.
+fn ignore_bad_window<T>(result: Result<T, Error>) -> Result<Option<T>, Error> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(Error::Reply(ReplyError::X11Error(error)))
+        if error.error_kind == x11rb::protocol::ErrorKind::Window => Ok(None),
+        Err(error) => Err(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_every_condition() {
+        let window = WindowInfo {
+            id: 1,
+            instance_name: Some("Navigator".to_string()),
+            class: Some("firefox".to_string()),
+            title: Some("Some video - YouTube — Mozilla Firefox".to_string()),
+            pid: Some(1234),
+            executable: Some(PathBuf::from("/usr/lib/firefox/firefox")),
+            fullscreen: false
+        };
+
+        assert!(Rule::new(1.0).matches(&window));
+        assert!(Rule { class: Some("firefox".to_string()), ..Rule::new(1.0) }.matches(&window));
+        assert!(Rule { class: Some("Navigator".to_string()), ..Rule::new(1.0) }.matches(&window));
+        assert!(!Rule { class: Some("fire".to_string()), ..Rule::new(1.0) }.matches(&window));
+        assert!(Rule {
+            class: Some("firefox".to_string()),
+            title: Some("YouTube".to_string()),
+            ..Rule::new(1.0)
+        }.matches(&window));
+        assert!(!Rule {
+            class: Some("firefox".to_string()),
+            title: Some("Twitch".to_string()),
+            ..Rule::new(1.0)
+        }.matches(&window));
+        assert!(Rule { executable: Some("firefox".to_string()), ..Rule::new(1.0) }
+            .matches(&window));
+        assert!(Rule { executable: Some("/usr/lib/firefox/firefox".to_string()), ..Rule::new(1.0) }
+            .matches(&window));
+        assert!(!Rule { executable: Some("/usr/bin/firefox".to_string()), ..Rule::new(1.0) }
+            .matches(&window));
+        assert!(!Rule { executable: Some("firefox".to_string()), ..Rule::new(1.0) }
+            .matches(&WindowInfo::default()));
+        assert!(!Rule { fullscreen: true, ..Rule::new(1.0) }.matches(&window));
+        assert!(Rule { fullscreen: true, ..Rule::new(1.0) }
+            .matches(&WindowInfo { fullscreen: true, ..window }));
+    }
+}
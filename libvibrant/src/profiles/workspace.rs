@@ -0,0 +1,185 @@
+use super::{is_root, watch_roots, Adjustment, Atoms, Defaults};
+use crate::{Error, Event, Instance};
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{AtomEnum, ConnectionExt as _};
+
+/// Identifies a virtual desktop.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Desktop {
+    /// The number of the desktop, starting at 0.
+    Index(u32),
+    /// The name of the desktop, as the window manager reports it in `_NET_DESKTOP_NAMES`.
+    Name(String)
+}
+
+/// Adjusts displays while a virtual desktop is shown.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DesktopRule {
+    pub desktop: Desktop,
+    /// The name of the display to adjust, every display if None.
+    pub output: Option<String>,
+    pub adjustment: Adjustment
+}
+
+impl DesktopRule {
+    /// Returns if the rule applies to the named display while the given desktop is shown.
+    pub fn matches(&self, index: u32, name: Option<&str>, output: &str) -> bool {
+        let desktop = match &self.desktop {
+            Desktop::Index(desktop) => *desktop == index,
+            Desktop::Name(desktop) => Some(desktop.as_str()) == name
+        };
+        desktop && self.output.as_ref().is_none_or(|rule_output| rule_output == output)
+    }
+}
+
+/// Applies the first rule matching the current virtual desktop to every display, and restores
+/// displays that no rule matches. Displays that can't take the adjustment of a rule, like a
+/// matrix on a display driven through XNVCtrl, are left alone.
+///
+/// Events have to be fed to it through [`Workspaces::handle_event`].
+pub struct Workspaces<'a> {
+    instance: &'a Instance,
+    atoms: Atoms,
+    rules: Vec<DesktopRule>,
+    defaults: Defaults,
+    /// The index and name of the desktop the rules were applied for.
+    current: Option<(u32, Option<String>)>
+}
+
+impl<'a> Workspaces<'a> {
+    /// Starts watching the current desktop and applies its rules right away. This selects events
+    /// on the instance.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the server failed to answer or rejected an adjustment.
+    pub fn new(instance: &'a Instance, rules: Vec<DesktopRule>) -> Result<Workspaces<'a>, Error> {
+        let atoms = Atoms::new(instance.xcon())?.reply()?;
+        watch_roots(instance)?;
+
+        let mut workspaces = Workspaces {
+            instance,
+            atoms,
+            rules,
+            defaults: Defaults::default(),
+            current: None
+        };
+        workspaces.update()?;
+        Ok(workspaces)
+    }
+
+    /// Returns the rules, in the order they are tried.
+    pub fn rules(&self) -> &[DesktopRule] {
+        &self.rules
+    }
+
+    /// Reacts to an event of the instance. Every event has to be passed here, the ones that do
+    /// not concern desktops are ignored.
+    ///
+    /// Newly connected displays are picked up by refreshing the instance.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the server failed to answer or rejected an adjustment.
+    pub fn handle_event(&mut self, event: &Event) -> Result<(), Error> {
+        match event {
+            Event::WindowPropertyChanged { window, property }
+            if (*property == self.atoms._NET_CURRENT_DESKTOP ||
+                *property == self.atoms._NET_DESKTOP_NAMES) && is_root(self.instance, *window) => {
+                self.update()
+            }
+            Event::OutputConnected(_) => {
+                self.instance.refresh()?;
+                self.apply()
+            }
+            _ => Ok(())
+        }
+    }
+
+    /// Puts back how every display looked before a rule was applied.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the server rejected an adjustment.
+    pub fn restore(&mut self) -> Result<(), Error> {
+        self.current = None;
+        self.apply()
+    }
+
+    /// Reads the current desktop and applies its rules if it changed.
+    fn update(&mut self) -> Result<(), Error> {
+        let xcon = self.instance.xcon();
+        // desktops are managed on the root window of the first screen
+        let root = xcon.setup().roots[0].root;
+        let index = xcon.get_property(false, root, self.atoms._NET_CURRENT_DESKTOP,
+                                      AtomEnum::CARDINAL, 0, 1)?;
+        let names = xcon.get_property(false, root, self.atoms._NET_DESKTOP_NAMES,
+                                      self.atoms.UTF8_STRING, 0, u32::MAX / 4)?;
+
+        let index = index.reply()?.value32().and_then(|mut values| values.next());
+        let names = names.reply()?.value;
+        let current = index.map(|index| {
+            // the names are null terminated strings back to back
+            let name = names.split(|c| *c == 0)
+                .nth(index as usize)
+                .map(|name| String::from_utf8_lossy(name).into_owned());
+            (index, name)
+        });
+
+        if current == self.current {
+            return Ok(());
+        }
+
+        self.current = current;
+        self.apply()
+    }
+
+    /// Applies the rules of the current desktop to every display. Displays that don't support the
+    /// adjustment of a rule are left alone.
+    fn apply(&mut self) -> Result<(), Error> {
+        let rules = &self.rules;
+        for controller in self.instance.controllers() {
+            let rule = self.current.as_ref().and_then(|(index, name)| {
+                rules.iter()
+                    .find(|rule| rule.matches(*index, name.as_deref(), controller.get_name()))
+            });
+
+            let result = match rule {
+                Some(rule) => self.defaults.change(&controller, &rule.adjustment),
+                None => self.defaults.restore(&controller)
+            };
+            match result {
+                // a matrix on a display driven through XNVCtrl, the other displays still get it
+                Err(Error::Unsupported(_)) => {},
+                result => result?
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_desktop_and_output() {
+        let by_index = DesktopRule {
+            desktop: Desktop::Index(1),
+            output: None,
+            adjustment: Adjustment::Saturation(1.0)
+        };
+        assert!(by_index.matches(1, Some("media"), "DP-1"));
+        assert!(!by_index.matches(0, Some("media"), "DP-1"));
+
+        let by_name = DesktopRule {
+            desktop: Desktop::Name("media".to_string()),
+            output: Some("HDMI-1".to_string()),
+            adjustment: Adjustment::Saturation(2.0)
+        };
+        assert!(by_name.matches(3, Some("media"), "HDMI-1"));
+        assert!(!by_name.matches(3, Some("media"), "DP-1"));
+        assert!(!by_name.matches(3, None, "HDMI-1"));
+    }
+}
@@ -0,0 +1,279 @@
+//! Changing displays with the time of day, like redshift does, without a network connection.
+//!
+//! The position of the sun is calculated from the location with the approximations of the NOAA
+//! solar calculator, which are good to about a minute. Displays use the day settings while the
+//! sun is up, the night settings once it is [`NIGHT_ELEVATION`] below the horizon, and a mix of
+//! both during twilight.
+//!
+//! ```no_run
+//! use libvibrant::schedule::{Location, Period, Schedule};
+//! use std::time::SystemTime;
+//!
+//! let instance = libvibrant::Instance::new().unwrap();
+//! let schedule = Schedule {
+//!     location: Location { latitude: 52.52, longitude: 13.40 },
+//!     day: Period { temperature: 6500.0, saturation: 1.2 },
+//!     night: Period { temperature: 3500.0, saturation: 1.0 }
+//! };
+//! schedule.apply(&instance, SystemTime::now()).unwrap();
+//! ```
+
+use crate::color;
+use crate::instance::{ColorMatrix, ControllerRef, Error, Instance};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// At this elevation of the sun in degrees and above, it is day.
+pub const DAY_ELEVATION: f64 = 3.0;
+/// At this elevation of the sun in degrees and below it is night, the end of civil twilight.
+pub const NIGHT_ELEVATION: f64 = -6.0;
+/// The elevation of the center of the sun at sunrise and sunset, below the horizon because of
+/// refraction and the size of the sun.
+const HORIZON_ELEVATION: f64 = -0.833;
+
+/// A place on earth, in degrees. North and east are positive.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Location {
+    pub latitude: f64,
+    pub longitude: f64
+}
+
+/// What the displays look like at a time of day.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Period {
+    /// The color temperature in Kelvin, 6500 leaves colors unchanged.
+    pub temperature: f64,
+    pub saturation: f64
+}
+
+/// Returns the Julian century of a time, what the NOAA approximations are in.
+fn julian_century(time: SystemTime) -> f64 {
+    let seconds = match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_secs_f64(),
+        Err(before) => -before.duration().as_secs_f64()
+    };
+    let julian_day = seconds / 86400.0 + 2_440_587.5;
+    (julian_day - 2_451_545.0) / 36525.0
+}
+
+/// Returns the declination of the sun in degrees and the equation of time in minutes.
+fn sun_position(jc: f64) -> (f64, f64) {
+    let mean_longitude = (280.46646 + jc * (36000.76983 + jc * 0.0003032)).rem_euclid(360.0);
+    let mean_anomaly = 357.52911 + jc * (35999.05029 - 0.0001537 * jc);
+    let eccentricity = 0.016708634 - jc * (0.000042037 + 0.0000001267 * jc);
+    let anomaly = mean_anomaly.to_radians();
+    let center = anomaly.sin() * (1.914602 - jc * (0.004817 + 0.000014 * jc)) +
+        (2.0 * anomaly).sin() * (0.019993 - 0.000101 * jc) +
+        (3.0 * anomaly).sin() * 0.000289;
+
+    let omega = (125.04 - 1934.136 * jc).to_radians();
+    let apparent_longitude = mean_longitude + center - 0.00569 - 0.00478 * omega.sin();
+    let mean_obliquity = 23.0 +
+        (26.0 + (21.448 - jc * (46.815 + jc * (0.00059 - jc * 0.001813))) / 60.0) / 60.0;
+    let obliquity = (mean_obliquity + 0.00256 * omega.cos()).to_radians();
+    let declination = (obliquity.sin() * apparent_longitude.to_radians().sin()).asin();
+
+    let y = (obliquity / 2.0).tan().powi(2);
+    let longitude = mean_longitude.to_radians();
+    let equation_of_time = 4.0 * (y * (2.0 * longitude).sin() -
+        2.0 * eccentricity * anomaly.sin() +
+        4.0 * eccentricity * y * anomaly.sin() * (2.0 * longitude).cos() -
+        0.5 * y * y * (4.0 * longitude).sin() -
+        1.25 * eccentricity * eccentricity * (2.0 * anomaly).sin()).to_degrees();
+
+    (declination.to_degrees(), equation_of_time)
+}
+
+/// Returns how high the sun is above the horizon at a place and time, in degrees.
+pub fn sun_elevation(location: &Location, time: SystemTime) -> f64 {
+    let (declination, equation_of_time) = sun_position(julian_century(time));
+    let seconds = time.duration_since(UNIX_EPOCH).map(|since| since.as_secs_f64()).unwrap_or(0.0);
+    let minutes = (seconds / 60.0).rem_euclid(1440.0);
+
+    let solar_time = (minutes + equation_of_time + 4.0 * location.longitude).rem_euclid(1440.0);
+    let hour_angle = (solar_time / 4.0 - 180.0).to_radians();
+    let latitude = location.latitude.to_radians();
+    let declination = declination.to_radians();
+    let cos_zenith = latitude.sin() * declination.sin() +
+        latitude.cos() * declination.cos() * hour_angle.cos();
+    90.0 - cos_zenith.clamp(-1.0, 1.0).acos().to_degrees()
+}
+
+/// When the sun passes certain elevations on a day, in UTC. None where it doesn't on that day,
+/// like during midnight sun or polar night.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SunTimes {
+    /// The start of civil twilight.
+    pub dawn: Option<SystemTime>,
+    pub sunrise: Option<SystemTime>,
+    pub noon: SystemTime,
+    pub sunset: Option<SystemTime>,
+    /// The end of civil twilight.
+    pub dusk: Option<SystemTime>
+}
+
+impl SunTimes {
+    /// Calculates the times of the UTC day that contains the given time.
+    pub fn on(location: &Location, time: SystemTime) -> SunTimes {
+        let seconds = time.duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or(0);
+        let midnight = UNIX_EPOCH + Duration::from_secs(seconds - seconds % 86400);
+        let at = |minutes: f64| {
+            if minutes >= 0.0 {
+                midnight + Duration::from_secs_f64(minutes * 60.0)
+            }
+            else {
+                midnight - Duration::from_secs_f64(-minutes * 60.0)
+            }
+        };
+
+        // the sun moves little within a day, its position at noon is good enough for the others
+        let (_, equation_of_time) = sun_position(julian_century(at(720.0)));
+        let noon = 720.0 - 4.0 * location.longitude - equation_of_time;
+        let (declination, _) = sun_position(julian_century(at(noon)));
+
+        let crossing = |elevation: f64| {
+            let latitude = location.latitude.to_radians();
+            let declination = declination.to_radians();
+            let cos_hour_angle = (elevation.to_radians().sin() -
+                latitude.sin() * declination.sin()) / (latitude.cos() * declination.cos());
+            if !(-1.0..=1.0).contains(&cos_hour_angle) {
+                return (None, None);
+            }
+            let minutes = 4.0 * cos_hour_angle.acos().to_degrees();
+            (Some(at(noon - minutes)), Some(at(noon + minutes)))
+        };
+        let (sunrise, sunset) = crossing(HORIZON_ELEVATION);
+        let (dawn, dusk) = crossing(NIGHT_ELEVATION);
+
+        SunTimes {
+            dawn,
+            sunrise,
+            noon: at(noon),
+            sunset,
+            dusk
+        }
+    }
+}
+
+/// Day and night settings for a location.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Schedule {
+    pub location: Location,
+    pub day: Period,
+    pub night: Period
+}
+
+impl Schedule {
+    /// Returns how far into the night it is, 0.0 during the day and 1.0 at night.
+    pub fn night_progress(&self, time: SystemTime) -> f64 {
+        let elevation = sun_elevation(&self.location, time);
+        ((DAY_ELEVATION - elevation) / (DAY_ELEVATION - NIGHT_ELEVATION)).clamp(0.0, 1.0)
+    }
+
+    /// Returns the settings at a time, between the day and night ones during twilight.
+    pub fn period_at(&self, time: SystemTime) -> Period {
+        let progress = self.night_progress(time);
+        let mix = |day: f64, night: f64| day + (night - day) * progress;
+        Period {
+            temperature: mix(self.day.temperature, self.night.temperature),
+            saturation: mix(self.day.saturation, self.night.saturation)
+        }
+    }
+
+    /// Returns the color transform for a time, the temperature applied after the saturation.
+    pub fn matrix_at(&self, time: SystemTime) -> ColorMatrix {
+        let period = self.period_at(time);
+        color::multiply(&color::temperature(period.temperature),
+                        &color::saturation(period.saturation))
+    }
+
+    /// Sets a display to the settings of a time. Displays without color transforms, driven
+    /// through XNVCtrl, only get the saturation.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the server failed to answer or rejected the new values.
+    pub fn apply_to(&self, controller: &ControllerRef<'_>, time: SystemTime) -> Result<(), Error> {
+        match controller.set_color_transform(&self.matrix_at(time)) {
+            Err(Error::Unsupported(_)) => {
+                controller.set_saturation(self.period_at(time).saturation)
+            }
+            result => result
+        }
+    }
+
+    /// Sets every display to the settings of a time, see [`Schedule::apply_to`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the server failed to answer or rejected the new values.
+    pub fn apply(&self, instance: &Instance, time: SystemTime) -> Result<(), Error> {
+        for controller in instance.controllers() {
+            self.apply_to(&controller, time)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2024-06-21 00:00 UTC
+    const SOLSTICE: u64 = 1_718_928_000;
+
+    fn minutes(time: Option<SystemTime>) -> f64 {
+        let seconds = time.unwrap().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        (seconds - SOLSTICE) as f64 / 60.0
+    }
+
+    #[test]
+    fn calculates_sun_times() {
+        let london = Location {
+            latitude: 51.5074,
+            longitude: -0.1278
+        };
+        let times = SunTimes::on(&london, UNIX_EPOCH + Duration::from_secs(SOLSTICE + 3600));
+        // 03:43 and 20:21 UTC according to timeanddate.com
+        assert!((minutes(times.sunrise) - (3.0 * 60.0 + 43.0)).abs() < 3.0);
+        assert!((minutes(times.sunset) - (20.0 * 60.0 + 21.0)).abs() < 3.0);
+        assert!(minutes(times.dawn) < minutes(times.sunrise));
+        assert!(minutes(times.dusk) > minutes(times.sunset));
+
+        let noon = UNIX_EPOCH + Duration::from_secs(SOLSTICE + 12 * 3600);
+        // 90 - 51.5 + 23.44
+        assert!((sun_elevation(&london, noon) - 61.9).abs() < 0.5);
+
+        let tromso = Location {
+            latitude: 69.65,
+            longitude: 18.96
+        };
+        let times = SunTimes::on(&tromso, UNIX_EPOCH + Duration::from_secs(SOLSTICE));
+        assert_eq!((times.sunrise, times.sunset), (None, None));
+    }
+
+    #[test]
+    fn mixes_day_and_night() {
+        let schedule = Schedule {
+            location: Location {
+                latitude: 51.5074,
+                longitude: -0.1278
+            },
+            day: Period {
+                temperature: 6500.0,
+                saturation: 1.2
+            },
+            night: Period {
+                temperature: 3500.0,
+                saturation: 1.0
+            }
+        };
+        let noon = UNIX_EPOCH + Duration::from_secs(SOLSTICE + 12 * 3600);
+        let midnight = UNIX_EPOCH + Duration::from_secs(SOLSTICE);
+        assert_eq!(schedule.period_at(noon), schedule.day);
+        assert_eq!(schedule.period_at(midnight), schedule.night);
+
+        let times = SunTimes::on(&schedule.location, noon);
+        let twilight = schedule.night_progress(times.sunset.unwrap());
+        assert!(twilight > 0.0 && twilight < 1.0);
+    }
+}
@@ -0,0 +1,135 @@
+use clap::Parser;
+use libvibrant::profiles::Adjustment;
+use libvibrant::ControllerRef;
+use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+use signal_hook::iterator::exfiltrator::WithOrigin;
+use signal_hook::iterator::SignalsInfo;
+use signal_hook::low_level::siginfo::{Cause, Origin};
+use std::os::unix::process::ExitStatusExt;
+use std::process::{self, Command};
+use std::sync::Mutex;
+use std::thread;
+use vibrant::{find, parse_saturation, Result};
+
+/// Runs a command with a different saturation, and puts the previous saturation back once the
+/// command exits.
+#[derive(Parser)]
+#[command(name = "vibrant-run", version)]
+struct Args {
+    /// The X display to connect to, $DISPLAY if not given
+    #[arg(long)]
+    display: Option<String>,
+    /// A number like 2.5 or a percentage like 250%, 1.0 is the normal saturation
+    #[arg(long, value_parser = parse_saturation)]
+    saturation: f64,
+    /// The name of the output, like DP-1, or the identity of the monitor to change, can be
+    /// given several times. Every display is changed if it is not given.
+    #[arg(long)]
+    output: Vec<String>,
+    /// The command to run and its arguments
+    #[arg(last = true, required = true)]
+    command: Vec<String>
+}
+
+/// How the changed displays looked before, taken out once they have been restored.
+type Originals<'a> = Mutex<Option<Vec<(ControllerRef<'a>, Adjustment)>>>;
+
+/// Puts every display back the way it was, unless that already happened.
+fn restore(originals: &Originals<'_>) {
+    if let Some(originals) = originals.lock().unwrap().take() {
+        for (controller, adjustment) in originals {
+            if let Err(error) = adjustment.apply(&controller) {
+                eprintln!("vibrant-run: could not restore {}: {}", controller.get_name(), error);
+            }
+        }
+    }
+}
+
+/// Sets the saturation of every controller, remembering how it looked before.
+fn apply<'a>(controllers: &[ControllerRef<'a>], saturation: f64,
+             originals: &Originals<'a>) -> Result<()> {
+    for controller in controllers {
+        let original = Adjustment::current(controller)?;
+        originals.lock().unwrap().as_mut().unwrap().push((controller.clone(), original));
+        controller.set_saturation(saturation)?;
+    }
+    Ok(())
+}
+
+/// Returns the exit code of the wrapper for the exit status of the command, the way shells do it.
+fn exit_code(status: process::ExitStatus) -> i32 {
+    match (status.code(), status.signal()) {
+        (Some(code), _) => code,
+        (None, Some(signal)) => 128 + signal,
+        (None, None) => 1
+    }
+}
+
+/// Returns if a signal has to be passed on to the command. Ctrl-C and a hangup of the terminal
+/// come from the kernel, which sends them to the whole foreground process group, the command
+/// included, so passing those on would deliver them twice.
+fn should_forward(origin: &Origin) -> bool {
+    !matches!(origin.cause, Cause::Kernel)
+}
+
+fn run(args: Args) -> Result<i32> {
+    let instance = vibrant::connect(args.display.as_deref())?;
+    let controllers = if args.output.is_empty() {
+        instance.controllers()
+    }
+    else {
+        args.output.iter()
+            .map(|output| find(&instance, output))
+            .collect::<Result<Vec<_>>>()?
+    };
+
+    let originals: Originals<'_> = Mutex::new(Some(Vec::new()));
+    // listen before changing anything, so there is no moment where a signal leaves us changed
+    let mut signals = SignalsInfo::<WithOrigin>::new([SIGINT, SIGTERM, SIGHUP])?;
+    let signals_handle = signals.handle();
+
+    if let Err(error) = apply(&controllers, args.saturation, &originals) {
+        restore(&originals);
+        return Err(error);
+    }
+
+    let mut child = match Command::new(&args.command[0]).args(&args.command[1..]).spawn() {
+        Ok(child) => child,
+        Err(error) => {
+            restore(&originals);
+            return Err(format!("could not run {}: {}", args.command[0], error).into());
+        }
+    };
+    let pid = child.id() as libc::pid_t;
+
+    let status = thread::scope(|scope| {
+        scope.spawn(|| {
+            for origin in signals.forever() {
+                restore(&originals);
+                // the command decides if it exits, we stay around to report its status
+                if should_forward(&origin) {
+                    unsafe {
+                        libc::kill(pid, origin.signal);
+                    }
+                }
+            }
+        });
+
+        let status = child.wait();
+        signals_handle.close();
+        status
+    })?;
+
+    restore(&originals);
+    Ok(exit_code(status))
+}
+
+fn main() {
+    match run(Args::parse()) {
+        Ok(code) => process::exit(code),
+        Err(error) => {
+            eprintln!("vibrant-run: {}", error);
+            process::exit(1);
+        }
+    }
+}
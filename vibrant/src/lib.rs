@@ -0,0 +1,58 @@
+//! What the command line tools have in common.
+
+use libvibrant::{ControllerRef, Instance};
+use std::ffi::CString;
+
+pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// Connects to the given X display, or to $DISPLAY.
+pub fn connect(display: Option<&str>) -> Result<Instance> {
+    Ok(match display {
+        Some(display) => Instance::from_display_name(&CString::new(display)?)?,
+        None => Instance::new()?
+    })
+}
+
+/// Parses a saturation given as a number or a percentage.
+pub fn parse_saturation(value: &str) -> std::result::Result<f64, String> {
+    let saturation = match value.strip_suffix('%') {
+        Some(percentage) => percentage.trim().parse::<f64>().map(|percentage| percentage / 100.0),
+        None => value.parse::<f64>()
+    }.map_err(|_| format!("'{}' is neither a number nor a percentage", value))?;
+
+    if !saturation.is_finite() || saturation < 0.0 {
+        return Err(format!("saturation can not be {}", value));
+    }
+    Ok(saturation)
+}
+
+/// Finds a controller by the name of its output or the identity of its monitor.
+pub fn find<'a>(instance: &'a Instance, output: &str) -> Result<ControllerRef<'a>> {
+    for controller in instance.controllers() {
+        if controller.get_name() == output {
+            return Ok(controller);
+        }
+        if let Some(identity) = controller.identity()? {
+            if identity.to_string() == output {
+                return Ok(controller);
+            }
+        }
+    }
+
+    Err(format!("there is no display called {}, see `vibrant list`", output).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_saturation() {
+        assert_eq!(parse_saturation("1.5"), Ok(1.5));
+        assert_eq!(parse_saturation("150%"), Ok(1.5));
+        assert_eq!(parse_saturation("0"), Ok(0.0));
+        assert!(parse_saturation("-1").is_err());
+        assert!(parse_saturation("NaN").is_err());
+        assert!(parse_saturation("vivid").is_err());
+    }
+}
@@ -0,0 +1,198 @@
+use clap::{Parser, Subcommand};
+use libvibrant::{ControllerRef, Error};
+use serde::Serialize;
+use std::process;
+use vibrant::{find, parse_saturation, Result};
+
+/// Controls the saturation of displays on an X server.
+#[derive(Parser)]
+#[command(name = "vibrant", version)]
+struct Args {
+    /// The X display to connect to, $DISPLAY if not given
+    #[arg(long, global = true)]
+    display: Option<String>,
+    /// Print JSON instead of text
+    #[arg(long, global = true)]
+    json: bool,
+    #[command(subcommand)]
+    command: Command
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List every display whose saturation can be controlled
+    List,
+    /// Print the saturation of a display
+    Get {
+        /// The name of the output, like DP-1, or the identity of the monitor
+        output: String
+    },
+    /// Set the saturation of a display
+    Set {
+        /// The name of the output, like DP-1, or the identity of the monitor
+        output: String,
+        /// A number like 1.5 or a percentage like 150%, 1.0 is the normal saturation
+        #[arg(value_parser = parse_saturation)]
+        value: f64
+    },
+    /// Set every display, or only the given one, back to the normal saturation
+    Reset {
+        /// The name of the output, like DP-1, or the identity of the monitor
+        output: Option<String>
+    }
+}
+
+/// What we print about a display.
+#[derive(Serialize)]
+struct DisplayInfo {
+    name: String,
+    backend: String,
+    /// Manufacturer, product and serial of the monitor, see `Identity`.
+    identity: Option<String>,
+    model: Option<String>,
+    /// None if the driver currently does not offer saturation for the display.
+    saturation: Option<f64>
+}
+
+impl DisplayInfo {
+    fn query(controller: &ControllerRef<'_>) -> Result<DisplayInfo> {
+        let identity = controller.identity()?;
+        let saturation = match controller.get_saturation() {
+            Ok(saturation) => Some(saturation),
+            Err(Error::Unsupported(_)) => None,
+            Err(error) => return Err(error.into())
+        };
+
+        Ok(DisplayInfo {
+            name: controller.get_name().to_string(),
+            backend: controller.get_backend().to_string(),
+            identity: identity.as_ref().map(|identity| identity.to_string()),
+            model: identity.and_then(|identity| identity.name),
+            saturation
+        })
+    }
+}
+
+/// Formats a saturation for humans.
+fn format_saturation(saturation: Option<f64>) -> String {
+    match saturation {
+        Some(saturation) => format!("{:.2}", saturation),
+        None => String::from("unavailable")
+    }
+}
+
+/// Returns if a command is about a single display, whose JSON is then an object instead of an
+/// array.
+fn names_one_display(command: &Command) -> bool {
+    matches!(command,
+             Command::Get { .. } | Command::Set { .. } | Command::Reset { output: Some(_) })
+}
+
+/// Returns the JSON of the displays, the first one on its own if `single` is set.
+fn to_json(displays: &[DisplayInfo], single: bool) -> serde_json::Result<String> {
+    match displays {
+        [display] if single => serde_json::to_string_pretty(display),
+        _ => serde_json::to_string_pretty(displays)
+    }
+}
+
+fn print_displays(displays: &[DisplayInfo], single: bool, json: bool) -> Result<()> {
+    if json {
+        println!("{}", to_json(displays, single)?);
+        return Ok(());
+    }
+
+    for display in displays {
+        print!("{} ({}): {}", display.name, display.backend,
+               format_saturation(display.saturation));
+        match (&display.identity, &display.model) {
+            (Some(identity), Some(model)) => println!(", {} {}", model, identity),
+            (Some(identity), None) => println!(", {}", identity),
+            _ => println!()
+        }
+    }
+    Ok(())
+}
+
+fn run(args: Args) -> Result<()> {
+    let instance = vibrant::connect(args.display.as_deref())?;
+    let single = names_one_display(&args.command);
+
+    match args.command {
+        Command::List => {
+            let displays = instance.controllers().iter()
+                .map(DisplayInfo::query)
+                .collect::<Result<Vec<_>>>()?;
+            print_displays(&displays, single, args.json)
+        }
+        Command::Get { output } => {
+            let display = DisplayInfo::query(&find(&instance, &output)?)?;
+            if args.json {
+                print_displays(&[display], single, true)?;
+            }
+            else {
+                println!("{}", format_saturation(display.saturation));
+            }
+            Ok(())
+        }
+        Command::Set { output, value } => {
+            let controller = find(&instance, &output)?;
+            controller.set_saturation(value)?;
+            if args.json {
+                print_displays(&[DisplayInfo::query(&controller)?], single, true)?;
+            }
+            Ok(())
+        }
+        Command::Reset { output } => {
+            let controllers = match output {
+                Some(output) => vec![find(&instance, &output)?],
+                None => instance.controllers()
+            };
+            for controller in &controllers {
+                controller.set_saturation(1.0)?;
+            }
+            if args.json {
+                let displays = controllers.iter()
+                    .map(DisplayInfo::query)
+                    .collect::<Result<Vec<_>>>()?;
+                print_displays(&displays, single, true)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+fn main() {
+    if let Err(error) = run(Args::parse()) {
+        eprintln!("vibrant: {}", error);
+        process::exit(1);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prints_single_displays_as_objects() {
+        let display = || DisplayInfo {
+            name: "DP-1".to_string(),
+            backend: "CTM".to_string(),
+            identity: None,
+            model: None,
+            saturation: Some(1.0)
+        };
+        let shape = |args: &[&str], displays: &[DisplayInfo]| {
+            let args = Args::try_parse_from(args).unwrap();
+            to_json(displays, names_one_display(&args.command)).unwrap().chars().next().unwrap()
+        };
+
+        assert_eq!(shape(&["vibrant", "--json", "get", "DP-1"], &[display()]), '{');
+        assert_eq!(shape(&["vibrant", "--json", "set", "DP-1", "150%"], &[display()]), '{');
+        assert_eq!(shape(&["vibrant", "--json", "reset", "DP-1"], &[display()]), '{');
+        // commands about every display print an array, even when there is only one
+        assert_eq!(shape(&["vibrant", "--json", "reset"], &[display()]), '[');
+        assert_eq!(shape(&["vibrant", "--json", "list"], &[display(), display()]), '[');
+    }
+}
@@ -0,0 +1,228 @@
+//! Decides what leased displays show. The bus and the control socket change displays through the
+//! [`Arbiter`] and the rules of the daemon ask it first, so a lease can't be undone behind its
+//! back.
+
+use crate::daemon::TOLERANCE;
+use crate::displays::{DisplayError, Displays};
+use crate::leases::Leases;
+use libvibrant::profiles::Adjustment;
+use libvibrant::ColorMatrix;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, MutexGuard};
+use vibrantd::protocol::{DisplayState, Priority};
+
+/// Returns what a display shows, the matrix if it has one.
+fn current(display: &DisplayState) -> Adjustment {
+    match display.matrix {
+        Some(matrix) => Adjustment::Transform(matrix),
+        None => Adjustment::Saturation(display.saturation)
+    }
+}
+
+/// Returns if a display shows an adjustment, give or take what the backend can store.
+fn shows(display: &DisplayState, adjustment: &Adjustment) -> bool {
+    match adjustment {
+        Adjustment::Saturation(saturation) => (display.saturation - saturation).abs() <= TOLERANCE,
+        Adjustment::Transform(matrix) => display.matrix.is_some_and(|current| {
+            current.iter().flatten().zip(matrix.iter().flatten())
+                .all(|(current, wanted)| (current - wanted).abs() <= TOLERANCE)
+        })
+    }
+}
+
+#[derive(Default)]
+struct State {
+    leases: Leases,
+    /// What leased displays go back to once their last lease is gone, by output name.
+    baselines: HashMap<String, Adjustment>
+}
+
+/// The displays as everyone but the leases sees them. Setting a display that is held by a lease
+/// fails with [`DisplayError::Leased`].
+pub struct Arbiter {
+    displays: Arc<dyn Displays>,
+    state: Mutex<State>
+}
+
+impl Arbiter {
+    pub fn new(displays: Arc<dyn Displays>) -> Arbiter {
+        Arbiter {
+            displays,
+            state: Mutex::new(State::default())
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+
+    fn apply(&self, output: &str, adjustment: &Adjustment) -> Result<(), DisplayError> {
+        match adjustment {
+            Adjustment::Saturation(saturation) => self.displays.set_saturation(output, *saturation),
+            Adjustment::Transform(matrix) => self.displays.set_color_transform(output, matrix)
+        }
+    }
+
+    /// Makes a display show its winning lease, or its baseline once no lease is left.
+    fn arbitrate(&self, state: &mut State, output: &str) -> Result<(), DisplayError> {
+        match state.leases.winner(output) {
+            Some(lease) => {
+                if !shows(&self.displays.get(output)?, &lease.adjustment) {
+                    self.apply(output, &lease.adjustment)?;
+                }
+            }
+            None => {
+                if let Some(baseline) = state.baselines.remove(output) {
+                    self.apply(output, &baseline)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets a display that is not leased, fails with [`DisplayError::Leased`] otherwise.
+    fn set(&self, output: &str, adjustment: &Adjustment) -> Result<(), DisplayError> {
+        // held while setting, so no lease sneaks in between
+        let state = self.lock();
+        if let Some(lease) = state.leases.winner(output) {
+            return Err(DisplayError::Leased {
+                output: output.to_string(),
+                priority: lease.priority
+            });
+        }
+        self.apply(output, adjustment)
+    }
+
+    /// Adds a lease of a client on a display and makes the display show the winning lease.
+    /// Returns the id of the lease.
+    pub fn acquire(&self, owner: u64, output: &str, priority: Priority,
+                   adjustment: Adjustment) -> Result<u64, DisplayError> {
+        let mut state = self.lock();
+        let display = self.displays.get(output)?;
+        state.baselines.entry(output.to_string()).or_insert_with(|| current(&display));
+
+        let id = state.leases.acquire(owner, output, priority, adjustment);
+        if let Err(error) = self.arbitrate(&mut state, output) {
+            state.leases.release(owner, id);
+            let _ = self.arbitrate(&mut state, output);
+            return Err(error);
+        }
+        Ok(id)
+    }
+
+    /// Releases a lease of a client, the display shows the next lease or its baseline.
+    pub fn release(&self, owner: u64, id: u64) -> Result<(), DisplayError> {
+        let mut state = self.lock();
+        let output = state.leases.release(owner, id).ok_or(DisplayError::NoLease(id))?;
+        self.arbitrate(&mut state, &output)
+    }
+
+    /// Releases every lease of a client that went away.
+    pub fn release_all(&self, owner: u64) {
+        let mut state = self.lock();
+        for output in state.leases.release_all(owner) {
+            if let Err(error) = self.arbitrate(&mut state, &output) {
+                eprintln!("vibrantd: failed to release the leases on {}: {}", output, error);
+            }
+        }
+    }
+
+    /// Undoes a change to a leased display made by a program that doesn't go through us. The
+    /// change is what the display goes back to once the leases are gone.
+    pub fn enforce(&self, output: &str) -> Result<(), DisplayError> {
+        let mut state = self.lock();
+        let adjustment = match state.leases.winner(output) {
+            Some(lease) => lease.adjustment.clone(),
+            None => return Ok(())
+        };
+        let display = self.displays.get(output)?;
+        if !shows(&display, &adjustment) {
+            state.baselines.insert(output.to_string(), current(&display));
+            self.apply(output, &adjustment)?;
+        }
+        Ok(())
+    }
+
+    /// Makes leased displays that were plugged in again show their leases. Leases stay while
+    /// their display is unplugged.
+    pub fn reconnected(&self) -> Result<(), DisplayError> {
+        let mut state = self.lock();
+        for output in state.leases.outputs() {
+            if self.displays.get(&output).is_ok() {
+                self.arbitrate(&mut state, &output)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the names of the displays that are held by a lease.
+    pub fn leased(&self) -> HashSet<String> {
+        self.lock().leases.outputs().into_iter().collect()
+    }
+
+    /// Makes a leased display go back to an adjustment once its leases are gone, instead of
+    /// what it showed before. Returns false and does nothing if the display is not leased.
+    pub fn defer(&self, output: &str, adjustment: &Adjustment) -> bool {
+        let mut state = self.lock();
+        if state.leases.winner(output).is_none() {
+            return false;
+        }
+        state.baselines.insert(output.to_string(), adjustment.clone());
+        true
+    }
+}
+
+impl Displays for Arbiter {
+    fn list(&self) -> Result<Vec<DisplayState>, DisplayError> {
+        self.displays.list()
+    }
+
+    fn set_saturation(&self, name: &str, saturation: f64) -> Result<(), DisplayError> {
+        self.set(name, &Adjustment::Saturation(saturation))
+    }
+
+    fn set_color_transform(&self, name: &str, matrix: &ColorMatrix) -> Result<(), DisplayError> {
+        self.set(name, &Adjustment::Transform(*matrix))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::displays::FakeDisplays;
+
+    #[test]
+    fn refuses_leased_displays() {
+        let displays = Arc::new(FakeDisplays(Mutex::new(vec![DisplayState {
+            name: "DP-1".to_string(),
+            output: 67,
+            backend: "CTM".to_string(),
+            identity: None,
+            saturation: 1.0,
+            matrix: None
+        }])));
+        let arbiter = Arbiter::new(displays.clone());
+        arbiter.set_saturation("DP-1", 1.5).unwrap();
+
+        let lease = arbiter.acquire(1, "DP-1", Priority::GameWrapper,
+                                    Adjustment::Saturation(2.0)).unwrap();
+        assert!(matches!(arbiter.set_saturation("DP-1", 1.0), Err(DisplayError::Leased { .. })));
+        assert_eq!(arbiter.get("DP-1").unwrap().saturation, 2.0);
+        assert!(matches!(arbiter.release(2, lease), Err(DisplayError::NoLease(_))));
+        assert_eq!(arbiter.leased().into_iter().collect::<Vec<_>>(), vec!["DP-1".to_string()]);
+
+        arbiter.release(1, lease).unwrap();
+        assert_eq!(arbiter.get("DP-1").unwrap().saturation, 1.5);
+        arbiter.set_saturation("DP-1", 1.0).unwrap();
+
+        // the daemon waits for the leases to be gone
+        assert!(!arbiter.defer("DP-1", &Adjustment::Saturation(1.3)));
+        let lease = arbiter.acquire(1, "DP-1", Priority::UserDefault,
+                                    Adjustment::Saturation(2.0)).unwrap();
+        assert!(arbiter.defer("DP-1", &Adjustment::Saturation(1.3)));
+        assert_eq!(arbiter.get("DP-1").unwrap().saturation, 2.0);
+        arbiter.release(1, lease).unwrap();
+        assert_eq!(arbiter.get("DP-1").unwrap().saturation, 1.3);
+        assert!(arbiter.leased().is_empty());
+    }
+}
@@ -0,0 +1,199 @@
+//! A client for the control socket of a running daemon.
+//!
+//! ```no_run
+//! use vibrantd::client::Client;
+//!
+//! let mut client = Client::connect().unwrap();
+//! for display in client.list().unwrap() {
+//!     println!("{}: {}", display.name, display.saturation);
+//! }
+//! client.set_saturation("DP-1", 1.5).unwrap();
+//! ```
+
+use crate::protocol::{AcquireParams, DisplayState, GetParams, Notification, NotificationMessage,
+                      Outcome, Priority, ReleaseParams, Request, Response, SetParams};
+use libvibrant::ColorMatrix;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ClientError {
+    #[error("$XDG_RUNTIME_DIR is not set, can not find the daemon")]
+    NoRuntimeDir,
+    #[error("Failed to talk to the daemon: {0}")]
+    Io(#[from] io::Error),
+    #[error("The daemon sent something we don't understand: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("The daemon closed the connection")]
+    Closed,
+    #[error("{message} (error {code})")]
+    Rpc {
+        code: i64,
+        message: String
+    }
+}
+
+/// A connection to the daemon.
+pub struct Client {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+    next_id: u64,
+    /// Notifications that arrived while waiting for a response.
+    notifications: VecDeque<Notification>
+}
+
+impl Client {
+    /// Connects to the daemon of this user.
+    pub fn connect() -> Result<Client, ClientError> {
+        let path = crate::protocol::socket_path().ok_or(ClientError::NoRuntimeDir)?;
+        Client::connect_to(&path)
+    }
+
+    /// Connects to a daemon listening on the given socket.
+    pub fn connect_to(path: &Path) -> Result<Client, ClientError> {
+        let writer = UnixStream::connect(path)?;
+        Ok(Client {
+            reader: BufReader::new(writer.try_clone()?),
+            writer,
+            next_id: 0,
+            notifications: VecDeque::new()
+        })
+    }
+
+    /// Reads the next message from the daemon.
+    fn read_message(&mut self) -> Result<Value, ClientError> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(ClientError::Closed);
+        }
+        Ok(serde_json::from_str(&line)?)
+    }
+
+    /// Sends a request and waits for its response, keeping notifications for later.
+    fn call<P: serde::Serialize, R: DeserializeOwned>(&mut self, method: &str,
+                                                       params: P) -> Result<R, ClientError> {
+        let id = self.next_id;
+        self.next_id += 1;
+        let request = Request {
+            jsonrpc: "2.0".to_string(),
+            id: Some(Value::from(id)),
+            method: method.to_string(),
+            params: serde_json::to_value(params)?
+        };
+        let mut line = serde_json::to_vec(&request)?;
+        line.push(b'\n');
+        self.writer.write_all(&line)?;
+
+        loop {
+            let message = self.read_message()?;
+            // notifications are the only messages without an id
+            if message.get("id").is_none() {
+                let message: NotificationMessage = serde_json::from_value(message)?;
+                self.notifications.push_back(message.notification);
+                continue;
+            }
+
+            let response: Response = serde_json::from_value(message)?;
+            if response.id != id {
+                continue;
+            }
+            return match response.outcome {
+                Outcome::Result(result) => Ok(serde_json::from_value(result)?),
+                Outcome::Error(error) => Err(ClientError::Rpc {
+                    code: error.code,
+                    message: error.message
+                })
+            };
+        }
+    }
+
+    /// Returns the state of every display.
+    pub fn list(&mut self) -> Result<Vec<DisplayState>, ClientError> {
+        self.call("list", Value::Null)
+    }
+
+    /// Returns the state of the named display.
+    pub fn get(&mut self, output: &str) -> Result<DisplayState, ClientError> {
+        self.call("get", GetParams {
+            output: output.to_string()
+        })
+    }
+
+    pub fn set_saturation(&mut self, output: &str, saturation: f64) -> Result<(), ClientError> {
+        self.call("set", SetParams {
+            output: output.to_string(),
+            saturation: Some(saturation),
+            matrix: None
+        })
+    }
+
+    /// Only supported by displays using the CTM backend.
+    pub fn set_color_transform(&mut self, output: &str,
+                               matrix: ColorMatrix) -> Result<(), ClientError> {
+        self.call("set", SetParams {
+            output: output.to_string(),
+            saturation: None,
+            matrix: Some(matrix)
+        })
+    }
+
+    /// Holds the saturation of a display until the lease is released or this connection
+    /// closes, returns the id of the lease.
+    pub fn acquire_saturation(&mut self, output: &str, priority: Priority,
+                              saturation: f64) -> Result<u64, ClientError> {
+        self.call("acquire", AcquireParams {
+            output: output.to_string(),
+            priority,
+            saturation: Some(saturation),
+            matrix: None
+        })
+    }
+
+    /// Like [`Client::acquire_saturation`] with a color transform.
+    pub fn acquire_color_transform(&mut self, output: &str, priority: Priority,
+                                   matrix: ColorMatrix) -> Result<u64, ClientError> {
+        self.call("acquire", AcquireParams {
+            output: output.to_string(),
+            priority,
+            saturation: None,
+            matrix: Some(matrix)
+        })
+    }
+
+    pub fn release(&mut self, lease: u64) -> Result<(), ClientError> {
+        self.call("release", ReleaseParams {
+            lease
+        })
+    }
+
+    /// Starts receiving notifications, see [`Client::next_notification`].
+    pub fn subscribe(&mut self) -> Result<(), ClientError> {
+        self.call::<_, bool>("subscribe", Value::Null).map(|_| ())
+    }
+
+    pub fn unsubscribe(&mut self) -> Result<(), ClientError> {
+        self.call::<_, bool>("unsubscribe", Value::Null).map(|_| ())
+    }
+
+    /// Blocks until the next notification arrives.
+    pub fn next_notification(&mut self) -> Result<Notification, ClientError> {
+        if let Some(notification) = self.notifications.pop_front() {
+            return Ok(notification);
+        }
+
+        loop {
+            let message = self.read_message()?;
+            // a response to a request we gave up on
+            if message.get("id").is_some() {
+                continue;
+            }
+            let message: NotificationMessage = serde_json::from_value(message)?;
+            return Ok(message.notification);
+        }
+    }
+}
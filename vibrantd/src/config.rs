@@ -0,0 +1,219 @@
+use libvibrant::color::NEUTRAL_TEMPERATURE;
+use libvibrant::profiles::Rule;
+use libvibrant::schedule::{Location, Period, Schedule};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::{env, fs, io};
+
+/// The configuration file of the daemon.
+///
+/// ```toml
+/// persist = true
+///
+/// [[monitor]]
+/// identity = "DEL-A0F2-ABC123"
+/// saturation = 1.2
+///
+/// [[monitor]]
+/// output = "HDMI-1"
+/// saturation = 1.5
+///
+/// [[rule]]
+/// class = "csgo_linux64"
+/// fullscreen = true
+/// saturation = 2.5
+///
+/// [schedule]
+/// latitude = 52.52
+/// longitude = 13.40
+/// night = { temperature = 3500 }
+/// ```
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Put the configured saturation back whenever something else changes it, e.g. a driver
+    /// that resets displays after a mode set.
+    pub persist: bool,
+    #[serde(rename = "monitor")]
+    pub monitors: Vec<Monitor>,
+    #[serde(rename = "rule")]
+    pub rules: Vec<RuleConfig>,
+    pub schedule: Option<ScheduleConfig>
+}
+
+/// The saturation of a monitor while no rule applies.
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Monitor {
+    /// The name of the connector, like DP-1.
+    pub output: Option<String>,
+    /// The identity of the monitor as `vibrant list` shows it, which follows the monitor from
+    /// port to port. A monitor without output and identity applies to every monitor.
+    pub identity: Option<String>,
+    pub saturation: f64
+}
+
+impl Monitor {
+    /// Returns if this entry is for the named output with the given monitor attached.
+    pub fn matches(&self, output: &str, identity: Option<&str>) -> bool {
+        self.output.as_ref().is_none_or(|name| name == output) &&
+            self.identity.as_ref().is_none_or(|name| Some(name.as_str()) == identity)
+    }
+}
+
+/// A rule of [`libvibrant::profiles`], see [`Rule`] for what the fields mean.
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RuleConfig {
+    pub class: Option<String>,
+    pub title: Option<String>,
+    pub executable: Option<String>,
+    #[serde(default)]
+    pub fullscreen: bool,
+    pub saturation: f64
+}
+
+impl From<&RuleConfig> for Rule {
+    fn from(config: &RuleConfig) -> Rule {
+        Rule {
+            class: config.class.clone(),
+            title: config.title.clone(),
+            executable: config.executable.clone(),
+            fullscreen: config.fullscreen,
+            saturation: config.saturation
+        }
+    }
+}
+
+/// Day and night settings, see [`libvibrant::schedule`].
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduleConfig {
+    pub latitude: f64,
+    pub longitude: f64,
+    #[serde(default)]
+    pub day: PeriodConfig,
+    #[serde(default)]
+    pub night: PeriodConfig
+}
+
+/// The saturation scales the one of the monitor entry, so 1.0 keeps it.
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PeriodConfig {
+    pub temperature: f64,
+    pub saturation: f64
+}
+
+impl Default for PeriodConfig {
+    fn default() -> PeriodConfig {
+        PeriodConfig {
+            temperature: NEUTRAL_TEMPERATURE,
+            saturation: 1.0
+        }
+    }
+}
+
+impl From<&PeriodConfig> for Period {
+    fn from(config: &PeriodConfig) -> Period {
+        Period {
+            temperature: config.temperature,
+            saturation: config.saturation
+        }
+    }
+}
+
+impl Config {
+    /// Returns where the configuration is read from if no path is given,
+    /// `$XDG_CONFIG_HOME/vibrantd/config.toml`.
+    pub fn default_path() -> PathBuf {
+        let config_home = env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+            .unwrap_or_else(|| PathBuf::from("/etc/xdg"));
+        config_home.join("vibrantd").join("config.toml")
+    }
+
+    /// Reads the configuration, a file that does not exist is an empty configuration.
+    pub fn load(path: &Path) -> Result<Config, String> {
+        match fs::read_to_string(path) {
+            Ok(text) => {
+                toml::from_str(&text).map_err(|error| format!("{}: {}", path.display(), error))
+            }
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(error) => Err(format!("{}: {}", path.display(), error))
+        }
+    }
+
+    /// Returns the saturation of the first monitor entry for the given output.
+    pub fn saturation_for(&self, output: &str, identity: Option<&str>) -> Option<f64> {
+        self.monitors.iter()
+            .find(|monitor| monitor.matches(output, identity))
+            .map(|monitor| monitor.saturation)
+    }
+
+    pub fn rules(&self) -> Vec<Rule> {
+        self.rules.iter().map(Rule::from).collect()
+    }
+
+    pub fn schedule(&self) -> Option<Schedule> {
+        self.schedule.as_ref().map(|schedule| Schedule {
+            location: Location {
+                latitude: schedule.latitude,
+                longitude: schedule.longitude
+            },
+            day: Period::from(&schedule.day),
+            night: Period::from(&schedule.night)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_config() {
+        let config: Config = toml::from_str(r#"
+            persist = true
+
+            [[monitor]]
+            identity = "DEL-A0F2-ABC123"
+            saturation = 1.2
+
+            [[monitor]]
+            output = "HDMI-1"
+            saturation = 1.5
+
+            [[monitor]]
+            saturation = 1.1
+
+            [[rule]]
+            class = "csgo_linux64"
+            fullscreen = true
+            saturation = 2.5
+
+            [schedule]
+            latitude = 52.52
+            longitude = 13.40
+            night = { temperature = 3500 }
+        "#).unwrap();
+
+        assert!(config.persist);
+        assert_eq!(config.saturation_for("DP-1", Some("DEL-A0F2-ABC123")), Some(1.2));
+        assert_eq!(config.saturation_for("HDMI-1", None), Some(1.5));
+        assert_eq!(config.saturation_for("DP-2", None), Some(1.1));
+        assert_eq!(config.rules(), vec![Rule {
+            class: Some("csgo_linux64".to_string()),
+            fullscreen: true,
+            ..Rule::new(2.5)
+        }]);
+        let schedule = config.schedule().unwrap();
+        assert_eq!(schedule.day.temperature, NEUTRAL_TEMPERATURE);
+        assert_eq!((schedule.night.temperature, schedule.night.saturation), (3500.0, 1.0));
+
+        assert_eq!(toml::from_str::<Config>("").unwrap(), Config::default());
+        assert!(toml::from_str::<Config>("[[monitor]]\nsaturation = 1.0\ncolour = 1").is_err());
+    }
+}
@@ -0,0 +1,206 @@
+use crate::arbiter::Arbiter;
+use crate::config::Config;
+use libvibrant::profiles::{Adjustment, Profiles};
+use libvibrant::{color, ControllerBackend, ControllerRef, Error, Event, Instance};
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+/// How far the saturation read back may be off from what was set, the XNVCtrl backend only
+/// stores it in steps of about 0.003.
+pub const TOLERANCE: f64 = 0.01;
+
+/// How often the schedule is followed, twilight takes long enough for this to look smooth.
+const TICK: Duration = Duration::from_secs(60);
+
+/// Keeps the displays the way the configuration says. Displays held by a lease are left to the
+/// arbiter, what the configuration wants for them is applied once the leases are gone.
+pub struct Daemon<'a> {
+    instance: &'a Instance,
+    arbiter: Arc<Arbiter>,
+    config: Config,
+    profiles: Profiles<'a>,
+    /// The outputs whose defaults have been applied.
+    known: HashSet<u32>,
+    /// The names of the displays held by a lease, as of the last [`Daemon::leases_changed`].
+    leased: HashSet<String>
+}
+
+impl<'a> Daemon<'a> {
+    /// Applies the configuration to every display and starts following the focused window.
+    pub fn new(instance: &'a Instance, arbiter: Arc<Arbiter>,
+               config: Config) -> Result<Daemon<'a>, Error> {
+        // the defaults go first, profiles restore whatever they found once a rule stops applying
+        let mut known = HashSet::new();
+        apply_defaults(instance, &arbiter, &config, &mut known)?;
+        let leased = arbiter.leased();
+        let profiles = Profiles::holding(instance, config.rules(), held(instance, &leased))?;
+
+        Ok(Daemon {
+            instance,
+            arbiter,
+            config,
+            profiles,
+            known,
+            leased
+        })
+    }
+
+    /// Switches to a new configuration.
+    pub fn reload(&mut self, config: Config) -> Result<(), Error> {
+        self.profiles.restore()?;
+        self.known.clear();
+        apply_defaults(self.instance, &self.arbiter, &config, &mut self.known)?;
+        self.profiles = Profiles::holding(self.instance, config.rules(),
+                                          held(self.instance, &self.leased))?;
+        self.config = config;
+        Ok(())
+    }
+
+    /// Catches up with leases that were acquired or released since the last call. Has to be
+    /// called whenever the leases may have changed.
+    pub fn leases_changed(&mut self) -> Result<(), Error> {
+        let leased = self.arbiter.leased();
+        if leased == self.leased {
+            return Ok(());
+        }
+        let previous = std::mem::replace(&mut self.leased, leased);
+
+        for controller in self.instance.controllers() {
+            let name = controller.get_name();
+            match (previous.contains(name), self.leased.contains(name)) {
+                (false, true) => self.profiles.hold(controller.get_output_id()),
+                (true, false) => {
+                    self.profiles.release(controller.get_output_id())?;
+                    // what the display goes back to may be a change the daemon would undo
+                    if self.config.persist && self.profiles.active_rule().is_none() {
+                        keep(&self.arbiter, &self.config, &controller)?;
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Takes back everything the rules changed, the defaults stay.
+    pub fn shutdown(&mut self) -> Result<(), Error> {
+        self.profiles.restore()
+    }
+
+    pub fn handle_event(&mut self, event: &Event) -> Result<(), Error> {
+        match event {
+            Event::OutputConnected(_) => {
+                // one refresh is enough for the defaults and the profiles
+                self.instance.refresh()?;
+                apply_defaults(self.instance, &self.arbiter, &self.config, &mut self.known)?;
+                return self.profiles.outputs_changed();
+            }
+            Event::OutputDisconnected(output) => {
+                self.known.remove(output);
+            }
+            Event::SaturationChanged(output)
+            if self.config.persist && self.profiles.active_rule().is_none() => {
+                // the arbiter undoes changes to leased displays itself
+                let controller = self.instance.controllers().into_iter()
+                    .find(|controller| controller.get_output_ids().contains(output))
+                    .filter(|controller| !self.leased.contains(controller.get_name()));
+                if let Some(controller) = controller {
+                    keep(&self.arbiter, &self.config, &controller)?;
+                }
+            }
+            _ => {}
+        }
+
+        self.profiles.handle_event(event)
+    }
+
+    /// Returns how long until [`Daemon::tick`] wants to be called, None if it doesn't.
+    pub fn tick_interval(&self) -> Option<Duration> {
+        self.config.schedule.as_ref().map(|_| TICK)
+    }
+
+    /// Follows the schedule, unless a rule is in charge of the displays right now.
+    pub fn tick(&mut self) -> Result<(), Error> {
+        if self.profiles.active_rule().is_some() {
+            return Ok(());
+        }
+        for controller in self.instance.controllers() {
+            keep(&self.arbiter, &self.config, &controller)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns what a display should look like now, None if the configuration doesn't care.
+fn target_for(config: &Config,
+              controller: &ControllerRef<'_>) -> Result<Option<Adjustment>, Error> {
+    let identity = controller.identity()?.map(|identity| identity.to_string());
+    let saturation = config.saturation_for(controller.get_name(), identity.as_deref());
+
+    let schedule = match config.schedule() {
+        Some(schedule) => schedule,
+        None => return Ok(saturation.map(Adjustment::Saturation))
+    };
+    let period = schedule.period_at(SystemTime::now());
+    let saturation = saturation.unwrap_or(1.0) * period.saturation;
+    Ok(Some(match controller.get_backend() {
+        ControllerBackend::CTM => {
+            Adjustment::Transform(color::multiply(&color::temperature(period.temperature),
+                                                  &color::saturation(saturation)))
+        }
+        ControllerBackend::XNVCtrl => Adjustment::Saturation(saturation)
+    }))
+}
+
+/// Returns the output ids of the leased displays, for [`Profiles::holding`].
+fn held(instance: &Instance, leased: &HashSet<String>) -> HashSet<u32> {
+    instance.controllers().into_iter()
+        .filter(|controller| leased.contains(controller.get_name()))
+        .map(|controller| controller.get_output_id())
+        .collect()
+}
+
+/// Puts a display back to what it should look like if something else changed it. A leased
+/// display goes back to it once its leases are gone.
+fn keep(arbiter: &Arbiter, config: &Config, controller: &ControllerRef<'_>) -> Result<(), Error> {
+    let target = match target_for(config, controller)? {
+        Some(target) => target,
+        None => return Ok(())
+    };
+    // leases are only acquired on the main loop, so none comes in before the target is set
+    if arbiter.defer(controller.get_name(), &target) {
+        return Ok(());
+    }
+    let unchanged = match (&target, Adjustment::current(controller)?) {
+        (Adjustment::Transform(target), Adjustment::Transform(current)) => {
+            target.iter().flatten().zip(current.iter().flatten())
+                .all(|(target, current)| (target - current).abs() <= TOLERANCE)
+        }
+        (Adjustment::Saturation(target), _) => {
+            (controller.get_saturation()? - target).abs() <= TOLERANCE
+        }
+        _ => false
+    };
+    if !unchanged {
+        target.apply(controller)?;
+    }
+    Ok(())
+}
+
+/// Applies the configured saturation to every display that is not known yet, or once its
+/// leases are gone.
+fn apply_defaults(instance: &Instance, arbiter: &Arbiter, config: &Config,
+                  known: &mut HashSet<u32>) -> Result<(), Error> {
+    for controller in instance.controllers() {
+        if !known.insert(controller.get_output_id()) {
+            continue;
+        }
+        if let Some(target) = target_for(config, &controller)? {
+            if !arbiter.defer(controller.get_name(), &target) {
+                target.apply(&controller)?;
+            }
+        }
+    }
+    Ok(())
+}
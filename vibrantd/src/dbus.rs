@@ -0,0 +1,337 @@
+//! The `org.libvibrant.Vibrant1` service on the session bus.
+//!
+//! The object at [`PATH`] lists the controllers, gets and sets their saturation or color
+//! transform by name, and emits `SaturationChanged` and `ControllersChanged`. Every controller
+//! is also exported at `/org/libvibrant/Vibrant1/Controllers/<output id>` with its state as
+//! properties.
+
+use crate::displays::{DisplayError, Displays};
+use libvibrant::{ColorMatrix, Error};
+use std::convert::TryFrom;
+use std::sync::Arc;
+use zbus::blocking::connection::Builder;
+use zbus::blocking::Connection;
+use zbus::fdo;
+use zbus::object_server::SignalEmitter;
+use zbus::zvariant::{ObjectPath, OwnedObjectPath};
+
+pub const BUS_NAME: &str = "org.libvibrant.Vibrant1";
+pub const PATH: &str = "/org/libvibrant/Vibrant1";
+
+impl From<DisplayError> for fdo::Error {
+    fn from(error: DisplayError) -> fdo::Error {
+        match error {
+            DisplayError::NotFound(_) => fdo::Error::InvalidArgs(error.to_string()),
+            DisplayError::Leased { .. } => fdo::Error::AccessDenied(error.to_string()),
+            DisplayError::NoLease(_) => fdo::Error::Failed(error.to_string()),
+            DisplayError::Vibrant(Error::Unsupported(_)) => {
+                fdo::Error::NotSupported(error.to_string())
+            }
+            DisplayError::Vibrant(_) => fdo::Error::Failed(error.to_string())
+        }
+    }
+}
+
+/// Flattens a matrix row by row.
+fn to_array(matrix: &ColorMatrix) -> Vec<f64> {
+    matrix.iter().flatten().copied().collect()
+}
+
+fn from_array(values: &[f64]) -> fdo::Result<ColorMatrix> {
+    if values.len() != 9 {
+        return Err(fdo::Error::InvalidArgs(
+            format!("a color transform has 9 values, not {}", values.len())));
+    }
+    let mut matrix = [[0.0; 3]; 3];
+    for (i, value) in values.iter().enumerate() {
+        matrix[i / 3][i % 3] = *value;
+    }
+    Ok(matrix)
+}
+
+fn controller_path(output: u32) -> OwnedObjectPath {
+    ObjectPath::try_from(format!("{}/Controllers/{}", PATH, output))
+        .expect("output ids make valid paths")
+        .into()
+}
+
+/// The object at [`PATH`].
+struct Service {
+    displays: Arc<dyn Displays>
+}
+
+#[zbus::interface(name = "org.libvibrant.Vibrant1")]
+impl Service {
+    /// Returns the path and name of every controller.
+    fn list_controllers(&self) -> fdo::Result<Vec<(OwnedObjectPath, String)>> {
+        Ok(self.displays.list()?.into_iter()
+            .map(|display| (controller_path(display.output), display.name))
+            .collect())
+    }
+
+    fn get_saturation(&self, name: &str) -> fdo::Result<f64> {
+        Ok(self.displays.get(name)?.saturation)
+    }
+
+    fn set_saturation(&self, name: &str, saturation: f64) -> fdo::Result<()> {
+        Ok(self.displays.set_saturation(name, saturation)?)
+    }
+
+    /// Returns the 9 values of the matrix, row by row.
+    fn get_color_transform(&self, name: &str) -> fdo::Result<Vec<f64>> {
+        match self.displays.get(name)?.matrix {
+            Some(matrix) => Ok(to_array(&matrix)),
+            None => Err(fdo::Error::NotSupported(
+                format!("{} does not support color transforms", name)))
+        }
+    }
+
+    fn set_color_transform(&self, name: &str, matrix: Vec<f64>) -> fdo::Result<()> {
+        Ok(self.displays.set_color_transform(name, &from_array(&matrix)?)?)
+    }
+
+    #[zbus(signal)]
+    async fn saturation_changed(emitter: &SignalEmitter<'_>, name: &str,
+                                saturation: f64) -> zbus::Result<()>;
+
+    /// A display was connected or disconnected.
+    #[zbus(signal)]
+    async fn controllers_changed(emitter: &SignalEmitter<'_>) -> zbus::Result<()>;
+}
+
+/// The object of a single controller.
+struct Controller {
+    displays: Arc<dyn Displays>,
+    name: String
+}
+
+#[zbus::interface(name = "org.libvibrant.Vibrant1.Controller")]
+impl Controller {
+    #[zbus(property)]
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    #[zbus(property)]
+    fn output_id(&self) -> fdo::Result<u32> {
+        Ok(self.displays.get(&self.name)?.output)
+    }
+
+    #[zbus(property)]
+    fn backend(&self) -> fdo::Result<String> {
+        Ok(self.displays.get(&self.name)?.backend)
+    }
+
+    /// Empty if the monitor has no EDID.
+    #[zbus(property)]
+    fn identity(&self) -> fdo::Result<String> {
+        Ok(self.displays.get(&self.name)?.identity.unwrap_or_default())
+    }
+
+    #[zbus(property)]
+    fn saturation(&self) -> fdo::Result<f64> {
+        Ok(self.displays.get(&self.name)?.saturation)
+    }
+
+    #[zbus(property)]
+    fn set_saturation(&mut self, saturation: f64) -> fdo::Result<()> {
+        Ok(self.displays.set_saturation(&self.name, saturation)?)
+    }
+
+    /// Empty if the backend does not support color transforms.
+    #[zbus(property)]
+    fn color_transform(&self) -> fdo::Result<Vec<f64>> {
+        Ok(self.displays.get(&self.name)?.matrix.as_ref().map(to_array).unwrap_or_default())
+    }
+
+    #[zbus(property)]
+    fn set_color_transform(&mut self, matrix: Vec<f64>) -> fdo::Result<()> {
+        Ok(self.displays.set_color_transform(&self.name, &from_array(&matrix)?)?)
+    }
+}
+
+/// Our connection to the bus.
+pub struct Bus {
+    connection: Connection,
+    displays: Arc<dyn Displays>,
+    /// The controller objects that are exported, by output id.
+    exported: Vec<(u32, String)>
+}
+
+impl Bus {
+    /// Connects to the session bus and takes the name of the service.
+    pub fn session(displays: Arc<dyn Displays>) -> zbus::Result<Bus> {
+        Bus::new(Builder::session()?, displays)
+    }
+
+    /// Connects through the given builder and takes the name of the service.
+    pub fn new(builder: Builder<'_>, displays: Arc<dyn Displays>) -> zbus::Result<Bus> {
+        let connection = builder
+            .name(BUS_NAME)?
+            .serve_at(PATH, Service { displays: displays.clone() })?
+            .build()?;
+
+        let mut bus = Bus {
+            connection,
+            displays,
+            exported: Vec::new()
+        };
+        bus.export()?;
+        Ok(bus)
+    }
+
+    /// Exports an object for every controller, and removes the ones of controllers that are
+    /// gone.
+    fn export(&mut self) -> zbus::Result<()> {
+        let displays = self.displays.list().map_err(fdo::Error::from)?;
+        let object_server = self.connection.object_server();
+
+        for (output, name) in &self.exported {
+            if !displays.iter().any(|display| display.output == *output && display.name == *name) {
+                object_server.remove::<Controller, _>(controller_path(*output))?;
+            }
+        }
+        for display in &displays {
+            object_server.at(controller_path(display.output), Controller {
+                displays: self.displays.clone(),
+                name: display.name.clone()
+            })?;
+        }
+
+        self.exported = displays.into_iter().map(|display| (display.output, display.name))
+            .collect();
+        Ok(())
+    }
+
+    /// Announces that displays were connected or disconnected.
+    pub fn controllers_changed(&mut self) -> zbus::Result<()> {
+        self.export()?;
+        let service = self.connection.object_server().interface::<_, Service>(PATH)?;
+        async_io::block_on(Service::controllers_changed(service.signal_emitter()))
+    }
+
+    /// Announces that the saturation of the display with the given output id changed.
+    pub fn saturation_changed(&self, output: u32) -> zbus::Result<()> {
+        let display = match self.displays.list().map_err(fdo::Error::from)?.into_iter()
+            .find(|display| display.output == output) {
+            Some(display) => display,
+            None => return Ok(())
+        };
+
+        let object_server = self.connection.object_server();
+        let service = object_server.interface::<_, Service>(PATH)?;
+        async_io::block_on(Service::saturation_changed(service.signal_emitter(), &display.name,
+                                                       display.saturation))?;
+
+        if let Ok(controller) = object_server
+            .interface::<_, Controller>(controller_path(output)) {
+            let emitter = controller.signal_emitter();
+            let controller = controller.get();
+            async_io::block_on(controller.saturation_changed(emitter))?;
+            async_io::block_on(controller.color_transform_changed(emitter))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::displays::FakeDisplays;
+    use std::io::{BufRead, BufReader};
+    use std::process::{Child, Command, Stdio};
+    use std::sync::Mutex;
+    use vibrantd::protocol::DisplayState;
+    use zbus::blocking::Proxy;
+
+    /// A private session bus that goes away with the test.
+    struct DbusDaemon(Child);
+
+    impl Drop for DbusDaemon {
+        fn drop(&mut self) {
+            let _ = self.0.kill();
+            let _ = self.0.wait();
+        }
+    }
+
+    /// Starts a bus, returns None if dbus-daemon is not installed.
+    fn start_bus() -> Option<(DbusDaemon, String)> {
+        let child = Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address=1"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .ok()?;
+        let mut daemon = DbusDaemon(child);
+
+        let mut address = String::new();
+        BufReader::new(daemon.0.stdout.as_mut().unwrap()).read_line(&mut address).unwrap();
+        Some((daemon, address.trim().to_string()))
+    }
+
+    #[test]
+    fn serves_controllers() {
+        let (_daemon, address) = match start_bus() {
+            Some(bus) => bus,
+            None => {
+                eprintln!("dbus-daemon is not installed, skipping");
+                return;
+            }
+        };
+
+        let displays = Arc::new(FakeDisplays(Mutex::new(vec![DisplayState {
+            name: "DP-1".to_string(),
+            output: 67,
+            backend: "CTM".to_string(),
+            identity: Some("DEL-A0F2-ABC123".to_string()),
+            saturation: 1.0,
+            matrix: Some([[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]])
+        }])));
+        let mut bus = Bus::new(Builder::address(address.as_str()).unwrap(), displays.clone())
+            .unwrap();
+
+        let client = Builder::address(address.as_str()).unwrap().build().unwrap();
+        let service = Proxy::new(&client, BUS_NAME, PATH, BUS_NAME).unwrap();
+
+        let controllers: Vec<(OwnedObjectPath, String)> =
+            service.call("ListControllers", &()).unwrap();
+        assert_eq!(controllers, vec![(controller_path(67), "DP-1".to_string())]);
+
+        service.call::<_, _, ()>("SetSaturation", &("DP-1", 2.5)).unwrap();
+        assert_eq!(service.call::<_, _, f64>("GetSaturation", &("DP-1",)).unwrap(), 2.5);
+        assert!(service.call::<_, _, f64>("GetSaturation", &("HDMI-1",)).is_err());
+
+        let matrix = vec![0.5, 0.25, 0.25, 0.25, 0.5, 0.25, 0.25, 0.25, 0.5];
+        service.call::<_, _, ()>("SetColorTransform", &("DP-1", matrix.clone())).unwrap();
+        assert_eq!(service.call::<_, _, Vec<f64>>("GetColorTransform", &("DP-1",)).unwrap(),
+                   matrix);
+        assert!(service.call::<_, _, ()>("SetColorTransform", &("DP-1", vec![1.0])).is_err());
+
+        // cached properties would hide whether the object is still there
+        let controller: Proxy<'_> = zbus::blocking::proxy::Builder::new(&client)
+            .destination(BUS_NAME).unwrap()
+            .path(controller_path(67)).unwrap()
+            .interface("org.libvibrant.Vibrant1.Controller").unwrap()
+            .cache_properties(zbus::proxy::CacheProperties::No)
+            .build()
+            .unwrap();
+        assert_eq!(controller.get_property::<String>("Identity").unwrap(), "DEL-A0F2-ABC123");
+        controller.set_property("Saturation", 1.5).unwrap();
+        assert_eq!(displays.get("DP-1").unwrap().saturation, 1.5);
+
+        let mut changes = service.receive_signal("SaturationChanged").unwrap();
+        bus.saturation_changed(67).unwrap();
+        let (name, saturation): (String, f64) = changes.next().unwrap().body().deserialize()
+            .unwrap();
+        assert_eq!((name.as_str(), saturation), ("DP-1", 1.5));
+
+        displays.0.lock().unwrap().clear();
+        let mut hotplugs = service.receive_signal("ControllersChanged").unwrap();
+        bus.controllers_changed().unwrap();
+        hotplugs.next().unwrap();
+        let controllers: Vec<(OwnedObjectPath, String)> =
+            service.call("ListControllers", &()).unwrap();
+        assert!(controllers.is_empty());
+        assert!(controller.get_property::<String>("Identity").is_err());
+    }
+}
@@ -0,0 +1,125 @@
+use libvibrant::{ColorMatrix, ControllerRef, Error, Instance};
+use std::io::Write;
+use std::os::unix::net::UnixStream;
+use std::sync::Arc;
+use thiserror::Error;
+use vibrantd::protocol::{DisplayState, Priority};
+
+#[derive(Error, Debug)]
+pub enum DisplayError {
+    #[error("there is no display called {0}")]
+    NotFound(String),
+    #[error("{output} is held by a lease of priority {priority:?}")]
+    Leased {
+        output: String,
+        priority: Priority
+    },
+    #[error("there is no lease {0} on this connection")]
+    NoLease(u64),
+    #[error(transparent)]
+    Vibrant(#[from] Error)
+}
+
+/// What the bus and the control socket need from the displays.
+pub trait Displays: Send + Sync + 'static {
+    /// Returns the state of every display.
+    fn list(&self) -> Result<Vec<DisplayState>, DisplayError>;
+    fn set_saturation(&self, name: &str, saturation: f64) -> Result<(), DisplayError>;
+    fn set_color_transform(&self, name: &str, matrix: &ColorMatrix) -> Result<(), DisplayError>;
+
+    /// Returns the state of the named display.
+    fn get(&self, name: &str) -> Result<DisplayState, DisplayError> {
+        self.list()?.into_iter()
+            .find(|display| display.name == name)
+            .ok_or_else(|| DisplayError::NotFound(name.to_string()))
+    }
+}
+
+/// The displays of an instance. Calls from the bus run on another thread than the main loop, so
+/// every call pokes the main loop afterwards, it may have to look at events that were read off
+/// the connection while waiting for our replies.
+pub struct InstanceDisplays {
+    pub instance: Arc<Instance>,
+    pub waker: UnixStream
+}
+
+impl InstanceDisplays {
+    fn wake<T>(&self, result: Result<T, DisplayError>) -> Result<T, DisplayError> {
+        let _ = (&self.waker).write(&[0]);
+        result
+    }
+
+    fn set<F>(&self, name: &str, set: F) -> Result<(), DisplayError>
+        where F: FnOnce(&ControllerRef<'_>) -> Result<(), Error> {
+        let controller = self.instance.controllers().into_iter()
+            .find(|controller| controller.get_name() == name)
+            .ok_or_else(|| DisplayError::NotFound(name.to_string()))?;
+        self.wake(set(&controller).map_err(DisplayError::from))
+    }
+}
+
+/// Returns the state of a display.
+fn state(controller: &ControllerRef<'_>) -> Result<DisplayState, Error> {
+    let matrix = match controller.get_color_transform() {
+        Ok(matrix) => Some(matrix),
+        Err(Error::Unsupported(_)) => None,
+        Err(error) => return Err(error)
+    };
+    Ok(DisplayState {
+        name: controller.get_name().to_string(),
+        output: controller.get_output_id(),
+        backend: controller.get_backend().to_string(),
+        identity: controller.identity()?.map(|identity| identity.to_string()),
+        saturation: controller.get_saturation()?,
+        matrix
+    })
+}
+
+impl Displays for InstanceDisplays {
+    fn list(&self) -> Result<Vec<DisplayState>, DisplayError> {
+        let list = self.instance.controllers().iter()
+            .map(state)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(DisplayError::from);
+        self.wake(list)
+    }
+
+    fn set_saturation(&self, name: &str, saturation: f64) -> Result<(), DisplayError> {
+        self.set(name, |controller| controller.set_saturation(saturation))
+    }
+
+    fn set_color_transform(&self, name: &str, matrix: &ColorMatrix) -> Result<(), DisplayError> {
+        self.set(name, |controller| controller.set_color_transform(matrix))
+    }
+}
+
+/// Displays that only exist in memory.
+#[cfg(test)]
+pub struct FakeDisplays(pub std::sync::Mutex<Vec<DisplayState>>);
+
+#[cfg(test)]
+impl FakeDisplays {
+    fn change<F: FnOnce(&mut DisplayState)>(&self, name: &str,
+                                             change: F) -> Result<(), DisplayError> {
+        let mut displays = self.0.lock().unwrap();
+        let display = displays.iter_mut().find(|display| display.name == name)
+            .ok_or_else(|| DisplayError::NotFound(name.to_string()))?;
+        change(display);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl Displays for FakeDisplays {
+    fn list(&self) -> Result<Vec<DisplayState>, DisplayError> {
+        Ok(self.0.lock().unwrap().clone())
+    }
+
+    fn set_saturation(&self, name: &str, saturation: f64) -> Result<(), DisplayError> {
+        self.change(name, |display| display.saturation = saturation)
+    }
+
+    fn set_color_transform(&self, name: &str, matrix: &ColorMatrix) -> Result<(), DisplayError> {
+        self.change(name, |display| display.matrix = Some(*matrix))
+    }
+}
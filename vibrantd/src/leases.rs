@@ -0,0 +1,103 @@
+use libvibrant::profiles::Adjustment;
+use vibrantd::protocol::Priority;
+
+/// A claim on the look of a display.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lease {
+    pub id: u64,
+    /// The connection that holds the lease.
+    pub owner: u64,
+    pub output: String,
+    pub priority: Priority,
+    pub adjustment: Adjustment
+}
+
+/// Every lease that is held, decides which one a display shows.
+#[derive(Debug, Default)]
+pub struct Leases {
+    next_id: u64,
+    /// In the order they were acquired.
+    leases: Vec<Lease>
+}
+
+impl Leases {
+    /// Adds a lease, returns its id.
+    pub fn acquire(&mut self, owner: u64, output: &str, priority: Priority,
+                   adjustment: Adjustment) -> u64 {
+        self.next_id += 1;
+        self.leases.push(Lease {
+            id: self.next_id,
+            owner,
+            output: output.to_string(),
+            priority,
+            adjustment
+        });
+        self.next_id
+    }
+
+    /// Removes a lease of the given owner, returns the output it was for.
+    pub fn release(&mut self, owner: u64, id: u64) -> Option<String> {
+        let index = self.leases.iter().position(|lease| lease.id == id && lease.owner == owner)?;
+        Some(self.leases.remove(index).output)
+    }
+
+    /// Removes every lease of the given owner, returns the outputs they were for.
+    pub fn release_all(&mut self, owner: u64) -> Vec<String> {
+        let mut outputs = Vec::new();
+        self.leases.retain(|lease| {
+            if lease.owner != owner {
+                return true;
+            }
+            if !outputs.contains(&lease.output) {
+                outputs.push(lease.output.clone());
+            }
+            false
+        });
+        outputs
+    }
+
+    /// Returns the lease that takes effect on an output, the one with the highest priority and
+    /// of those the most recent.
+    pub fn winner(&self, output: &str) -> Option<&Lease> {
+        self.leases.iter()
+            .filter(|lease| lease.output == output)
+            .max_by_key(|lease| (lease.priority, lease.id))
+    }
+
+    /// Returns the outputs that have leases.
+    pub fn outputs(&self) -> Vec<String> {
+        let mut outputs: Vec<String> = self.leases.iter().map(|lease| lease.output.clone())
+            .collect();
+        outputs.sort();
+        outputs.dedup();
+        outputs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn highest_priority_wins() {
+        let mut leases = Leases::default();
+        let game = leases.acquire(1, "DP-1", Priority::GameWrapper, Adjustment::Saturation(2.0));
+        let user = leases.acquire(2, "DP-1", Priority::UserDefault, Adjustment::Saturation(1.2));
+        leases.acquire(2, "HDMI-1", Priority::UserDefault, Adjustment::Saturation(1.1));
+        assert_eq!(leases.winner("DP-1").unwrap().id, game);
+        assert_eq!(leases.outputs(), vec!["DP-1", "HDMI-1"]);
+
+        // the most recent of equal priorities
+        let profile = leases.acquire(3, "DP-1", Priority::AppProfile, Adjustment::Saturation(0.5));
+        let newer = leases.acquire(3, "DP-1", Priority::AppProfile, Adjustment::Saturation(0.6));
+        assert_eq!(leases.release(1, game), Some("DP-1".to_string()));
+        assert_eq!(leases.winner("DP-1").unwrap().id, newer);
+
+        // only the owner can release a lease
+        assert_eq!(leases.release(2, profile), None);
+        assert_eq!(leases.release_all(3), vec!["DP-1"]);
+        assert_eq!(leases.winner("DP-1").unwrap().id, user);
+        assert_eq!(leases.release_all(2), vec!["DP-1", "HDMI-1"]);
+        assert!(leases.winner("DP-1").is_none());
+    }
+}
@@ -0,0 +1,4 @@
+//! Talking to a running vibrantd over its control socket.
+
+pub mod client;
+pub mod protocol;
@@ -0,0 +1,259 @@
+mod arbiter;
+mod config;
+mod daemon;
+mod dbus;
+mod displays;
+mod leases;
+mod rpc;
+
+use crate::arbiter::Arbiter;
+use crate::config::Config;
+use crate::daemon::Daemon;
+use crate::dbus::Bus;
+use crate::displays::InstanceDisplays;
+use crate::rpc::Server;
+use clap::Parser;
+use inotify::{Inotify, WatchMask};
+use libvibrant::x11rb::connection::Connection;
+use libvibrant::{Error, Event, Instance};
+use signal_hook::consts::{SIGINT, SIGTERM};
+use std::ffi::CString;
+use std::io::{self, Read};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Keeps the saturation of displays the way it is configured, and changes it while configured
+/// applications are focused.
+#[derive(Parser)]
+#[command(name = "vibrantd", version)]
+struct Args {
+    /// The X display to connect to, $DISPLAY if not given
+    #[arg(long)]
+    display: Option<String>,
+    /// The configuration file, $XDG_CONFIG_HOME/vibrantd/config.toml if not given
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// Do not offer the org.libvibrant.Vibrant1 service on the session bus
+    #[arg(long)]
+    no_dbus: bool,
+    /// Do not listen on the control socket in $XDG_RUNTIME_DIR
+    #[arg(long)]
+    no_socket: bool
+}
+
+/// Starts watching the directory of the configuration file. Editors tend to replace the file
+/// instead of writing to it, so watching the file itself would lose track of it.
+fn watch_config(path: &Path) -> io::Result<Inotify> {
+    let inotify = Inotify::init()?;
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new(".")
+    };
+    inotify.watches().add(dir, WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO |
+        WatchMask::DELETE)?;
+    Ok(inotify)
+}
+
+/// Returns if any of the pending file events concern the configuration file.
+fn config_changed(inotify: &mut Inotify, path: &Path) -> io::Result<bool> {
+    let mut buffer = [0; 4096];
+    let mut changed = false;
+    loop {
+        let events = match inotify.read_events(&mut buffer) {
+            Ok(events) => events,
+            Err(error) if error.kind() == io::ErrorKind::WouldBlock => return Ok(changed),
+            Err(error) => return Err(error)
+        };
+        for event in events {
+            changed |= event.name.is_some_and(|name| Some(name) == path.file_name());
+        }
+    }
+}
+
+/// Reads everything that is available on a non-blocking socket, returns if there was anything.
+fn drain(socket: &mut UnixStream) -> bool {
+    let mut drained = false;
+    while matches!(socket.read(&mut [0; 64]), Ok(read) if read > 0) {
+        drained = true;
+    }
+    drained
+}
+
+/// Tells the clients on the bus about an event.
+fn announce(bus: &mut Bus, event: &Event) -> zbus::Result<()> {
+    match event {
+        Event::SaturationChanged(output) => bus.saturation_changed(*output),
+        Event::OutputConnected(_) | Event::OutputDisconnected(_) => bus.controllers_changed(),
+        _ => Ok(())
+    }
+}
+
+/// Starts listening on the control socket.
+fn listen(arbiter: Arc<Arbiter>) -> io::Result<Server> {
+    let path = vibrantd::protocol::socket_path().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::NotFound, "$XDG_RUNTIME_DIR is not set")
+    })?;
+    Server::bind(&path, arbiter)
+}
+
+/// Waits until one of the descriptors has one of the given poll events, or the timeout passed.
+fn wait(fds: &[(RawFd, i16)], timeout: Option<Duration>) -> io::Result<()> {
+    let mut pollfds: Vec<_> = fds.iter()
+        .map(|(fd, events)| libc::pollfd {
+            fd: *fd,
+            events: *events,
+            revents: 0
+        })
+        .collect();
+
+    // safe, the descriptors stay open while we wait on them
+    let result = unsafe {
+        libc::poll(pollfds.as_mut_ptr(), pollfds.len() as libc::nfds_t,
+                   timeout.map_or(-1, |timeout| timeout.as_millis().min(i32::MAX as u128) as i32))
+    };
+    if result < 0 {
+        let error = io::Error::last_os_error();
+        if error.kind() != io::ErrorKind::Interrupted {
+            return Err(error);
+        }
+    }
+    Ok(())
+}
+
+fn run(args: Args) -> Result<(), Box<dyn std::error::Error>> {
+    let instance = Arc::new(match &args.display {
+        Some(display) => Instance::from_display_name(&CString::new(display.as_str())?)?,
+        None => Instance::new()?
+    });
+    let config_path = args.config.unwrap_or_else(Config::default_path);
+    let (mut wake, waker) = UnixStream::pair()?;
+    wake.set_nonblocking(true)?;
+    // everything that changes displays asks the arbiter first, so nothing undoes a lease
+    let arbiter = Arc::new(Arbiter::new(Arc::new(InstanceDisplays {
+        instance: instance.clone(),
+        waker
+    })));
+    let mut daemon = Daemon::new(&instance, arbiter.clone(), Config::load(&config_path)?)?;
+
+    let mut inotify = match watch_config(&config_path) {
+        Ok(inotify) => Some(inotify),
+        Err(error) => {
+            eprintln!("vibrantd: not reloading {} on changes: {}", config_path.display(), error);
+            None
+        }
+    };
+
+    let (mut signals, signal_sender) = UnixStream::pair()?;
+    signals.set_nonblocking(true)?;
+    for signal in [SIGINT, SIGTERM] {
+        signal_hook::low_level::pipe::register(signal, signal_sender.try_clone()?)?;
+    }
+
+    let mut bus = if args.no_dbus {
+        None
+    }
+    else {
+        match Bus::session(arbiter.clone()) {
+            Ok(bus) => Some(bus),
+            Err(error) => {
+                eprintln!("vibrantd: not offering {} on the session bus: {}", dbus::BUS_NAME,
+                          error);
+                None
+            }
+        }
+    };
+
+    let mut server = if args.no_socket {
+        None
+    }
+    else {
+        match listen(arbiter.clone()) {
+            Ok(server) => Some(server),
+            Err(error) => {
+                eprintln!("vibrantd: not listening on the control socket: {}", error);
+                None
+            }
+        }
+    };
+
+    let mut fds = vec![instance.as_raw_fd(), signals.as_raw_fd(), wake.as_raw_fd()];
+    fds.extend(inotify.as_ref().map(|inotify| inotify.as_raw_fd()));
+
+    let mut next_tick = Instant::now();
+    loop {
+        while let Some(event) = instance.poll_event()? {
+            match daemon.handle_event(&event) {
+                Ok(()) => {}
+                Err(error @ Error::Connection(_)) => return Err(error.into()),
+                Err(error) => eprintln!("vibrantd: {}", error)
+            }
+            if let Some(bus) = &mut bus {
+                if let Err(error) = announce(bus, &event) {
+                    eprintln!("vibrantd: failed to announce a change on the bus: {}", error);
+                }
+            }
+            if let Some(server) = &mut server {
+                server.notify(&event);
+            }
+        }
+
+        if let Some(server) = &mut server {
+            server.process();
+        }
+        if let Err(error) = daemon.leases_changed() {
+            eprintln!("vibrantd: failed to catch up with the leases: {}", error);
+        }
+
+        if let Some(inotify) = &mut inotify {
+            if config_changed(inotify, &config_path)? {
+                match Config::load(&config_path) {
+                    Ok(config) => {
+                        if let Err(error) = daemon.reload(config) {
+                            eprintln!("vibrantd: failed to apply the new configuration: {}", error);
+                        }
+                    }
+                    Err(error) => eprintln!("vibrantd: keeping the old configuration, {}", error)
+                }
+            }
+        }
+
+        let interval = daemon.tick_interval();
+        if interval.is_some() && Instant::now() >= next_tick {
+            if let Err(error) = daemon.tick() {
+                eprintln!("vibrantd: failed to follow the schedule: {}", error);
+            }
+        }
+        if let Some(interval) = interval {
+            while next_tick <= Instant::now() {
+                next_tick += interval;
+            }
+        }
+
+        if signals.read(&mut [0; 16]).is_ok() {
+            // the leases go first, what the rules changed underneath them is restored after
+            drop(server);
+            daemon.leases_changed()?;
+            daemon.shutdown()?;
+            return Ok(());
+        }
+
+        instance.xcon().flush().map_err(Error::from)?;
+        // calls from the bus may have read events off the connection in the meantime
+        if !drain(&mut wake) {
+            let mut events: Vec<_> = fds.iter().map(|fd| (*fd, libc::POLLIN)).collect();
+            events.extend(server.iter().flat_map(Server::fds));
+            wait(&events, interval.map(|_| next_tick.saturating_duration_since(Instant::now())))?;
+        }
+    }
+}
+
+fn main() {
+    if let Err(error) = run(Args::parse()) {
+        eprintln!("vibrantd: {}", error);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,205 @@
+//! The JSON-RPC 2.0 protocol spoken on the control socket of the daemon.
+//!
+//! Every message is a single line of JSON. The methods are:
+//!
+//! - `list`, returns a [`DisplayState`] for every display
+//! - `get`, with `{"output": "DP-1"}`, returns the [`DisplayState`] of one display
+//! - `set`, with `{"output": "DP-1", "saturation": 1.5}` or a `"matrix"` of 3 rows, returns null
+//! - `subscribe` and `unsubscribe`, start and stop [`Notification`]s on this connection
+//! - `acquire`, with the parameters of `set` and a [`Priority`], returns the id of a lease
+//! - `release`, with `{"lease": 3}`, returns null
+//!
+//! A lease keeps a display the way it asks for as long as no lease of a higher priority is
+//! held on the same display. When the winning lease is released, the next one takes effect
+//! again, and once the last one is gone the display goes back to what it was before. While a
+//! display is leased `set` fails with [`LEASED`], as do the setters of the D-Bus service, and
+//! the rules of the daemon leave it alone until the leases are gone. Changes made by programs
+//! that don't go through the daemon are undone, the display goes back to the last of them
+//! instead. Leases are released when their connection closes, so a program holding one can not
+//! leave a display behind.
+//!
+//! So the socket can be used from a shell as well:
+//!
+//! ```sh
+//! echo '{"jsonrpc": "2.0", "id": 1, "method": "list"}' | socat - UNIX-CONNECT:$XDG_RUNTIME_DIR/vibrantd.sock
+//! ```
+
+use libvibrant::ColorMatrix;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::env;
+use std::path::PathBuf;
+
+/// The message could not be parsed as JSON.
+pub const PARSE_ERROR: i64 = -32700;
+/// The message was JSON, but not a request.
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+/// The display could not be changed or read.
+pub const FAILED: i64 = -32000;
+/// There is no display with the given name.
+pub const NOT_FOUND: i64 = -32001;
+/// The backend of the display does not support what was asked, e.g. a matrix on XNVCtrl.
+pub const UNSUPPORTED: i64 = -32002;
+/// The display is held by a lease, see `acquire`.
+pub const LEASED: i64 = -32003;
+/// This connection holds no lease with the given id.
+pub const NO_LEASE: i64 = -32004;
+
+/// Returns where the daemon listens, `$XDG_RUNTIME_DIR/vibrantd.sock`. None if the runtime
+/// directory is not set, there is no other place that only the user can access.
+pub fn socket_path() -> Option<PathBuf> {
+    env::var_os("XDG_RUNTIME_DIR")
+        .filter(|dir| !dir.is_empty())
+        .map(|dir| PathBuf::from(dir).join("vibrantd.sock"))
+}
+
+/// What the daemon reports about a display.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DisplayState {
+    /// The name of the output, like DP-1.
+    pub name: String,
+    /// The RandR id of the output.
+    pub output: u32,
+    pub backend: String,
+    /// The identity of the monitor, see `libvibrant::Identity`.
+    pub identity: Option<String>,
+    pub saturation: f64,
+    /// None if the backend does not support color transforms.
+    pub matrix: Option<ColorMatrix>
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Request {
+    pub jsonrpc: String,
+    /// Requests without an id get no response.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<Value>,
+    pub method: String,
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub params: Value
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Outcome {
+    #[serde(rename = "result")]
+    Result(Value),
+    #[serde(rename = "error")]
+    Error(RpcError)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Response {
+    pub jsonrpc: String,
+    pub id: Value,
+    #[serde(flatten)]
+    pub outcome: Outcome
+}
+
+/// The parameters of `get`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GetParams {
+    pub output: String
+}
+
+/// The parameters of `set`, exactly one of saturation and matrix has to be given.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SetParams {
+    pub output: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub saturation: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub matrix: Option<ColorMatrix>
+}
+
+/// How much a lease matters, from least to most.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    /// What the user picked as the normal look of a display, e.g. in a settings application.
+    UserDefault,
+    /// A profile that follows the application in use.
+    AppProfile,
+    /// A wrapper around a single program, like vibrant-run around a game.
+    GameWrapper,
+    /// Needed for the user to see the screen properly, always wins.
+    Accessibility
+}
+
+/// The parameters of `acquire`, like those of `set` with a priority.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AcquireParams {
+    pub output: String,
+    pub priority: Priority,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub saturation: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub matrix: Option<ColorMatrix>
+}
+
+/// The parameters of `release`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReleaseParams {
+    pub lease: u64
+}
+
+/// Sent to subscribed connections, as JSON-RPC requests without an id.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "method", content = "params", rename_all = "snake_case")]
+pub enum Notification {
+    SaturationChanged {
+        output: String,
+        saturation: f64
+    },
+    /// A display was connected or disconnected.
+    ControllersChanged {}
+}
+
+/// A notification along with the version field every message carries.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NotificationMessage {
+    pub jsonrpc: String,
+    #[serde(flatten)]
+    pub notification: Notification
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_messages() {
+        let response = Response {
+            jsonrpc: "2.0".to_string(),
+            id: Value::from(1),
+            outcome: Outcome::Result(Value::Null)
+        };
+        assert_eq!(serde_json::to_string(&response).unwrap(),
+                   r#"{"jsonrpc":"2.0","id":1,"result":null}"#);
+
+        let response: Response = serde_json::from_str(
+            r#"{"jsonrpc":"2.0","id":2,"error":{"code":-32001,"message":"no"}}"#).unwrap();
+        assert_eq!(response.outcome, Outcome::Error(RpcError {
+            code: NOT_FOUND,
+            message: "no".to_string()
+        }));
+
+        let notification = NotificationMessage {
+            jsonrpc: "2.0".to_string(),
+            notification: Notification::SaturationChanged {
+                output: "DP-1".to_string(),
+                saturation: 1.5
+            }
+        };
+        let text = serde_json::to_string(&notification).unwrap();
+        assert_eq!(text, r#"{"jsonrpc":"2.0","method":"saturation_changed","params":{"output":"DP-1","saturation":1.5}}"#);
+        assert_eq!(serde_json::from_str::<NotificationMessage>(&text).unwrap(), notification);
+    }
+}
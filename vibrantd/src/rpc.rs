@@ -0,0 +1,444 @@
+//! The control socket, see [`vibrantd::protocol`] for what is spoken on it.
+
+use crate::arbiter::Arbiter;
+use crate::displays::{DisplayError, Displays};
+use libvibrant::profiles::Adjustment;
+use libvibrant::{Error, Event};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use vibrantd::protocol::{self, AcquireParams, GetParams, Notification, NotificationMessage,
+                         Outcome, ReleaseParams, Request, Response, RpcError, SetParams};
+
+/// Lines longer than this are not requests we know, the connection is dropped instead of
+/// buffering them.
+const MAX_LINE: usize = 64 * 1024;
+
+/// A connected client.
+struct Connection {
+    /// Identifies the leases of this client.
+    id: u64,
+    stream: UnixStream,
+    /// What was read but is not a full line yet.
+    input: Vec<u8>,
+    /// What could not be written yet.
+    output: Vec<u8>,
+    subscribed: bool,
+    closed: bool
+}
+
+impl Connection {
+    fn send<T: Serialize>(&mut self, message: &T) {
+        // our messages always serialize
+        serde_json::to_writer(&mut self.output, message).expect("messages serialize");
+        self.output.push(b'\n');
+    }
+
+    /// Reads what is available, returns the complete lines.
+    fn read_lines(&mut self) -> Vec<Vec<u8>> {
+        let mut buffer = [0; 4096];
+        loop {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => {
+                    self.closed = true;
+                    break;
+                }
+                Ok(read) => self.input.extend_from_slice(&buffer[..read]),
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => break,
+                Err(_) => {
+                    self.closed = true;
+                    break;
+                }
+            }
+        }
+
+        let mut lines = Vec::new();
+        while let Some(end) = self.input.iter().position(|byte| *byte == b'\n') {
+            let mut line: Vec<u8> = self.input.drain(..=end).collect();
+            line.pop();
+            lines.push(line);
+        }
+        if self.input.len() > MAX_LINE {
+            self.closed = true;
+        }
+        lines
+    }
+
+    /// Writes as much of the pending output as the socket takes.
+    fn flush(&mut self) {
+        while !self.output.is_empty() {
+            match self.stream.write(&self.output) {
+                Ok(written) => {
+                    self.output.drain(..written);
+                }
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => break,
+                Err(_) => {
+                    self.closed = true;
+                    break;
+                }
+            }
+        }
+    }
+}
+
+fn rpc_error(code: i64, message: impl ToString) -> RpcError {
+    RpcError {
+        code,
+        message: message.to_string()
+    }
+}
+
+impl From<DisplayError> for RpcError {
+    fn from(error: DisplayError) -> RpcError {
+        let code = match &error {
+            DisplayError::NotFound(_) => protocol::NOT_FOUND,
+            DisplayError::Leased { .. } => protocol::LEASED,
+            DisplayError::NoLease(_) => protocol::NO_LEASE,
+            DisplayError::Vibrant(Error::Unsupported(_)) => protocol::UNSUPPORTED,
+            DisplayError::Vibrant(_) => protocol::FAILED
+        };
+        rpc_error(code, error)
+    }
+}
+
+fn params<T: DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    serde_json::from_value(params).map_err(|error| rpc_error(protocol::INVALID_PARAMS, error))
+}
+
+fn to_value<T: Serialize>(value: T) -> Result<Value, RpcError> {
+    Ok(serde_json::to_value(value).expect("results serialize"))
+}
+
+/// Returns the adjustment of `set` and `acquire`, which take exactly one of the two.
+fn adjustment(saturation: Option<f64>,
+              matrix: Option<libvibrant::ColorMatrix>) -> Result<Adjustment, RpcError> {
+    match (saturation, matrix) {
+        (Some(saturation), None) => Ok(Adjustment::Saturation(saturation)),
+        (None, Some(matrix)) => Ok(Adjustment::Transform(matrix)),
+        _ => Err(rpc_error(protocol::INVALID_PARAMS, "give either a saturation or a matrix"))
+    }
+}
+
+/// Listens on the control socket and serves its clients, without ever blocking.
+pub struct Server {
+    listener: UnixListener,
+    path: PathBuf,
+    arbiter: Arc<Arbiter>,
+    connections: Vec<Connection>,
+    next_id: u64
+}
+
+impl Server {
+    /// Listens on the given path. A socket left behind by a daemon that is gone is replaced, one
+    /// that still has a daemon behind it is not.
+    pub fn bind(path: &Path, arbiter: Arc<Arbiter>) -> io::Result<Server> {
+        if path.exists() {
+            if UnixStream::connect(path).is_ok() {
+                return Err(io::Error::new(io::ErrorKind::AddrInUse,
+                                          format!("another daemon listens on {}", path.display())));
+            }
+            fs::remove_file(path)?;
+        }
+
+        let listener = UnixListener::bind(path)?;
+        listener.set_nonblocking(true)?;
+        Ok(Server {
+            listener,
+            path: path.to_path_buf(),
+            arbiter,
+            connections: Vec::new(),
+            next_id: 0
+        })
+    }
+
+    /// Returns the descriptors to wait on along with the poll events to wait for.
+    pub fn fds(&self) -> Vec<(RawFd, i16)> {
+        let mut fds = vec![(self.listener.as_raw_fd(), libc::POLLIN)];
+        for connection in &self.connections {
+            let mut events = libc::POLLIN;
+            if !connection.output.is_empty() {
+                events |= libc::POLLOUT;
+            }
+            fds.push((connection.stream.as_raw_fd(), events));
+        }
+        fds
+    }
+
+    /// Accepts new clients, answers what they asked for and writes pending output.
+    pub fn process(&mut self) {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    if stream.set_nonblocking(true).is_ok() {
+                        self.next_id += 1;
+                        self.connections.push(Connection {
+                            id: self.next_id,
+                            stream,
+                            input: Vec::new(),
+                            output: Vec::new(),
+                            subscribed: false,
+                            closed: false
+                        });
+                    }
+                }
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => break,
+                Err(error) => {
+                    eprintln!("vibrantd: failed to accept a client: {}", error);
+                    break;
+                }
+            }
+        }
+
+        let arbiter = &self.arbiter;
+        for connection in &mut self.connections {
+            for line in connection.read_lines() {
+                if let Some(response) = handle_line(arbiter, connection, &line) {
+                    connection.send(&response);
+                }
+            }
+            connection.flush();
+        }
+        self.connections.retain(|connection| {
+            if connection.closed {
+                arbiter.release_all(connection.id);
+            }
+            !connection.closed
+        });
+    }
+
+    /// Keeps leased displays the way their leases say and tells the subscribed clients about
+    /// an event.
+    pub fn notify(&mut self, event: &Event) {
+        let arbiter = &self.arbiter;
+        let display = match event {
+            Event::SaturationChanged(output) => arbiter.list().ok().and_then(|displays| {
+                displays.into_iter().find(|display| display.output == *output)
+            }),
+            _ => None
+        };
+        let result = match (event, &display) {
+            (Event::SaturationChanged(_), Some(display)) => arbiter.enforce(&display.name),
+            (Event::OutputConnected(_), _) => arbiter.reconnected(),
+            _ => Ok(())
+        };
+        if let Err(error) = result {
+            eprintln!("vibrantd: failed to keep a leased display: {}", error);
+        }
+
+        if !self.connections.iter().any(|connection| connection.subscribed) {
+            return;
+        }
+
+        let notification = match event {
+            Event::SaturationChanged(_) => {
+                // the value the leases left, not the one that was briefly there
+                let display = display.and_then(|display| {
+                    self.arbiter.get(&display.name).ok()
+                });
+                match display {
+                    Some(display) => Notification::SaturationChanged {
+                        output: display.name,
+                        saturation: display.saturation
+                    },
+                    None => return
+                }
+            }
+            Event::OutputConnected(_) | Event::OutputDisconnected(_) => {
+                Notification::ControllersChanged {}
+            }
+            _ => return
+        };
+
+        let message = NotificationMessage {
+            jsonrpc: "2.0".to_string(),
+            notification
+        };
+        for connection in self.connections.iter_mut().filter(|connection| connection.subscribed) {
+            connection.send(&message);
+            connection.flush();
+        }
+    }
+}
+
+impl Drop for Server {
+    /// Leases end with the daemon, so the displays go back to what they showed before.
+    fn drop(&mut self) {
+        for connection in &self.connections {
+            self.arbiter.release_all(connection.id);
+        }
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Handles a line from a client, returns the response if there should be one.
+fn handle_line(arbiter: &Arbiter, connection: &mut Connection,
+               line: &[u8]) -> Option<Response> {
+    let message: Value = match serde_json::from_slice(line) {
+        Ok(message) => message,
+        Err(error) => {
+            return Some(Response {
+                jsonrpc: "2.0".to_string(),
+                id: Value::Null,
+                outcome: Outcome::Error(rpc_error(protocol::PARSE_ERROR, error))
+            });
+        }
+    };
+    let id = message.get("id").cloned();
+
+    let outcome = match serde_json::from_value::<Request>(message) {
+        Ok(request) if request.jsonrpc == "2.0" => {
+            let outcome = call(arbiter, connection, request.method.as_str(), request.params);
+            // requests without an id are notifications, which get no response
+            request.id.as_ref()?;
+            outcome
+        }
+        Ok(_) => Err(rpc_error(protocol::INVALID_REQUEST, "only JSON-RPC 2.0 is supported")),
+        Err(error) => Err(rpc_error(protocol::INVALID_REQUEST, error))
+    };
+
+    Some(Response {
+        jsonrpc: "2.0".to_string(),
+        id: id.unwrap_or(Value::Null),
+        outcome: match outcome {
+            Ok(result) => Outcome::Result(result),
+            Err(error) => Outcome::Error(error)
+        }
+    })
+}
+
+fn call(arbiter: &Arbiter, connection: &mut Connection, method: &str,
+        params_value: Value) -> Result<Value, RpcError> {
+    match method {
+        "list" => to_value(arbiter.list()?),
+        "get" => {
+            let params: GetParams = params(params_value)?;
+            to_value(arbiter.get(&params.output)?)
+        }
+        "set" => {
+            let params: SetParams = params(params_value)?;
+            match adjustment(params.saturation, params.matrix)? {
+                Adjustment::Saturation(saturation) => {
+                    arbiter.set_saturation(&params.output, saturation)?
+                }
+                Adjustment::Transform(matrix) => {
+                    arbiter.set_color_transform(&params.output, &matrix)?
+                }
+            }
+            Ok(Value::Null)
+        }
+        "acquire" => {
+            let params: AcquireParams = params(params_value)?;
+            let adjustment = adjustment(params.saturation, params.matrix)?;
+            to_value(arbiter.acquire(connection.id, &params.output, params.priority,
+                                     adjustment)?)
+        }
+        "release" => {
+            let params: ReleaseParams = params(params_value)?;
+            arbiter.release(connection.id, params.lease)?;
+            Ok(Value::Null)
+        }
+        "subscribe" | "unsubscribe" => {
+            connection.subscribed = method == "subscribe";
+            Ok(Value::Bool(true))
+        }
+        _ => Err(rpc_error(protocol::METHOD_NOT_FOUND, format!("there is no method {}", method)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::displays::FakeDisplays;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{mpsc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+    use vibrantd::client::{Client, ClientError};
+    use vibrantd::protocol::{DisplayState, Priority};
+
+    #[test]
+    fn serves_clients() {
+        let path = std::env::temp_dir().join(format!("vibrantd-test-{}.sock", std::process::id()));
+        let displays = Arc::new(FakeDisplays(Mutex::new(vec![DisplayState {
+            name: "DP-1".to_string(),
+            output: 67,
+            backend: "CTM".to_string(),
+            identity: None,
+            saturation: 1.0,
+            matrix: None
+        }])));
+        let arbiter = Arc::new(Arbiter::new(displays.clone()));
+        let mut server = Server::bind(&path, arbiter.clone()).unwrap();
+        assert!(Server::bind(&path, arbiter.clone()).is_err());
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let (events, received) = mpsc::channel();
+        let thread = thread::spawn({
+            let stop = stop.clone();
+            move || {
+                while !stop.load(Ordering::SeqCst) {
+                    server.process();
+                    for event in received.try_iter() {
+                        server.notify(&event);
+                    }
+                    thread::sleep(Duration::from_millis(5));
+                }
+            }
+        });
+
+        let mut client = Client::connect_to(&path).unwrap();
+        assert_eq!(client.list().unwrap(), displays.list().unwrap());
+        client.set_saturation("DP-1", 2.0).unwrap();
+        assert_eq!(client.get("DP-1").unwrap().saturation, 2.0);
+        match client.get("HDMI-1") {
+            Err(ClientError::Rpc { code, .. }) => assert_eq!(code, protocol::NOT_FOUND),
+            other => panic!("expected an error, got {:?}", other)
+        }
+
+        client.subscribe().unwrap();
+        events.send(Event::SaturationChanged(67)).unwrap();
+        assert_eq!(client.next_notification().unwrap(), Notification::SaturationChanged {
+            output: "DP-1".to_string(),
+            saturation: 2.0
+        });
+
+        let mut game = Client::connect_to(&path).unwrap();
+        let lease = client.acquire_saturation("DP-1", Priority::UserDefault, 1.2).unwrap();
+        game.acquire_saturation("DP-1", Priority::GameWrapper, 3.0).unwrap();
+        assert_eq!(client.get("DP-1").unwrap().saturation, 3.0);
+        match client.set_saturation("DP-1", 1.0) {
+            Err(ClientError::Rpc { code, .. }) => assert_eq!(code, protocol::LEASED),
+            other => panic!("expected an error, got {:?}", other)
+        }
+        assert!(game.release(lease).is_err());
+
+        // changes from elsewhere are undone
+        displays.set_saturation("DP-1", 0.5).unwrap();
+        events.send(Event::SaturationChanged(67)).unwrap();
+        assert_eq!(client.next_notification().unwrap(), Notification::SaturationChanged {
+            output: "DP-1".to_string(),
+            saturation: 3.0
+        });
+
+        // the game exiting hands the display back to the next lease
+        drop(game);
+        while client.get("DP-1").unwrap().saturation != 1.2 {
+            thread::sleep(Duration::from_millis(5));
+        }
+        client.release(lease).unwrap();
+        assert_eq!(client.get("DP-1").unwrap().saturation, 0.5);
+
+        stop.store(true, Ordering::SeqCst);
+        thread.join().unwrap();
+        assert!(!path.exists());
+    }
+}